@@ -0,0 +1,1145 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::io::{stderr, Write};
+use std::ops::Range;
+use std::rc::Rc;
+
+use anyhow::{bail, ensure, Context, Result};
+use conllu::Error;
+use itertools::Itertools;
+use udgraph::graph::{Comment, DepTriple, Node, Sentence};
+use udgraph::token::Token;
+
+use crate::io::{EmptyNode, Words};
+
+/// A gold sentence paired with a predicted sentence.
+///
+/// The tokens of the predicted sentence are numbered as in the gold
+/// sentence. Gold tokens that do not have a counterpart in the predicted
+/// treebank are marked as unaligned. Predicted words that do not have a
+/// counterpart in the gold treebank are stored separately. Unaligned
+/// words cannot be correct, but are counted as gold or predicted words
+/// by metrics that report precision and recall.
+///
+/// The sentences are shared between a pair and its filtered copies.
+pub struct SentencePair {
+    pub gold: Rc<Sentence>,
+    pub predicted: Rc<Sentence>,
+    empty_nodes: Option<Rc<EmptyNodes>>,
+    unaligned_predicted: Rc<Vec<UnalignedWord>>,
+    aligned: Vec<bool>,
+    included: Vec<bool>,
+    included_unaligned: Vec<bool>,
+}
+
+/// The empty nodes of a sentence pair.
+#[derive(Default)]
+pub struct EmptyNodes {
+    pub gold: Vec<EmptyNode>,
+
+    /// Predicted empty nodes, numbered as in the gold sentence.
+    pub predicted: Vec<EmptyNode>,
+
+    /// Predicted empty nodes that follow a word that is not aligned to a
+    /// gold word.
+    pub unaligned: Vec<EmptyNode>,
+}
+
+/// A predicted word that is not aligned to a gold word.
+pub struct UnalignedWord {
+    pub token: Token,
+
+    /// The relation of the word, `_` when the word is attached without a
+    /// relation and `None` when the word does not have a head.
+    pub relation: Option<String>,
+}
+
+impl SentencePair {
+    /// Pair two sentences with identical tokenization.
+    pub fn new(gold: Sentence, predicted: Sentence) -> Result<Self> {
+        ensure!(
+            gold.len() == predicted.len(),
+            "Different number of tokens: {} {}",
+            gold.len(),
+            predicted.len()
+        );
+
+        let aligned = vec![true; gold.len()];
+
+        Ok(SentencePair::from_parts(
+            gold,
+            predicted,
+            aligned,
+            Vec::new(),
+            EmptyNodes::default(),
+        ))
+    }
+
+    /// Pair two sentences with identical tokenization, including their
+    /// empty nodes.
+    ///
+    /// Empty nodes are matched by their identifiers.
+    pub fn from_words(gold: Words, predicted: Words) -> Result<Self> {
+        let mut pair = SentencePair::new(gold.sentence, predicted.sentence)?;
+        pair.empty_nodes = Some(Rc::new(EmptyNodes {
+            gold: gold.empty_nodes,
+            predicted: predicted.empty_nodes,
+            unaligned: Vec::new(),
+        }));
+
+        Ok(pair)
+    }
+
+    fn from_parts(
+        gold: Sentence,
+        predicted: Sentence,
+        aligned: Vec<bool>,
+        unaligned_predicted: Vec<UnalignedWord>,
+        empty_nodes: EmptyNodes,
+    ) -> Self {
+        SentencePair {
+            included: vec![true; gold.len()],
+            included_unaligned: vec![true; unaligned_predicted.len()],
+            gold: Rc::new(gold),
+            predicted: Rc::new(predicted),
+            empty_nodes: Some(Rc::new(empty_nodes)),
+            unaligned_predicted: Rc::new(unaligned_predicted),
+            aligned,
+        }
+    }
+
+    /// Get the indices of gold tokens that are aligned to a predicted token.
+    pub fn aligned_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (1..self.gold.len()).filter(move |&idx| self.included[idx] && self.aligned[idx])
+    }
+
+    /// Get the indices of gold tokens that are not aligned to a predicted
+    /// token.
+    pub fn unaligned_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (1..self.gold.len()).filter(move |&idx| self.included[idx] && !self.aligned[idx])
+    }
+
+    /// Get the predicted words that are not aligned to a gold token.
+    pub fn unaligned_predicted(&self) -> impl Iterator<Item = &UnalignedWord> + '_ {
+        self.unaligned_predicted
+            .iter()
+            .zip(&self.included_unaligned)
+            .filter_map(|(word, &included)| if included { Some(word) } else { None })
+    }
+
+    /// Get the empty nodes of the pair.
+    ///
+    /// Empty nodes are not words, so filtered pairs do not have empty
+    /// nodes.
+    pub fn empty_nodes(&self) -> Option<&EmptyNodes> {
+        self.empty_nodes.as_deref()
+    }
+
+    /// Get a copy of the pair with the gold tokens for which `predicate`
+    /// holds and the unaligned predicted words for which
+    /// `unaligned_predicate` holds.
+    pub fn filter(
+        &self,
+        predicate: impl Fn(usize) -> bool,
+        unaligned_predicate: impl Fn(usize) -> bool,
+    ) -> SentencePair {
+        let mut included = self.included.clone();
+        for (idx, included) in included.iter_mut().enumerate() {
+            *included = *included && predicate(idx);
+        }
+
+        let mut included_unaligned = self.included_unaligned.clone();
+        for (idx, included) in included_unaligned.iter_mut().enumerate() {
+            *included = *included && unaligned_predicate(idx);
+        }
+
+        SentencePair {
+            gold: self.gold.clone(),
+            predicted: self.predicted.clone(),
+            empty_nodes: None,
+            unaligned_predicted: self.unaligned_predicted.clone(),
+            aligned: self.aligned.clone(),
+            included,
+            included_unaligned,
+        }
+    }
+
+    /// Get all predicted words that are not aligned to a gold token,
+    /// regardless of filtering.
+    pub fn all_unaligned_predicted(&self) -> &[UnalignedWord] {
+        &self.unaligned_predicted
+    }
+
+    /// Check whether all gold tokens are aligned to a predicted token.
+    pub fn is_fully_aligned(&self) -> bool {
+        self.aligned[1..].iter().all(|&aligned| aligned)
+    }
+
+    /// Check whether the gold token at `idx` is aligned to a predicted token.
+    pub fn is_aligned(&self, idx: usize) -> bool {
+        self.aligned[idx]
+    }
+}
+
+/// Get the `sent_id` of a sentence.
+pub fn sentence_id(sentence: &Sentence) -> Option<&str> {
+    sentence
+        .comments()
+        .iter()
+        .find_map(|comment| match comment {
+            Comment::AttrVal { attr, val } if attr == "sent_id" => Some(val.as_str()),
+            _ => None,
+        })
+}
+
+/// Sentences of treebanks, matched by their `sent_id`.
+pub struct SentenceMatching<S = Sentence> {
+    /// The matched sentences of each treebank. The sentences at the same
+    /// position in each treebank are matched.
+    pub treebanks: Vec<Vec<S>>,
+
+    /// Identifiers of the sentences of each treebank that could not be
+    /// matched. When sentences are matched by position, sentences without
+    /// a `sent_id` are identified by their 1-based position.
+    pub unmatched: Vec<Vec<String>>,
+
+    /// Whether the sentences were matched by position, because not all
+    /// sentences have a `sent_id`.
+    pub by_position: bool,
+}
+
+impl<S> SentenceMatching<S> {
+    /// Write the identifiers of unmatched sentences.
+    pub fn write_unmatched(&self, writer: &mut dyn Write, names: &[&str]) -> Result<()> {
+        if self.by_position {
+            writeln!(
+                writer,
+                "Not all sentences have a sent_id, matching sentences by position"
+            )?;
+        }
+
+        for (name, unmatched) in names.iter().zip(&self.unmatched) {
+            if unmatched.is_empty() {
+                continue;
+            }
+
+            writeln!(
+                writer,
+                "{} unmatched sentence(s) in {}: {}",
+                unmatched.len(),
+                name,
+                unmatched.join(" ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Match the sentences of treebanks by their `sent_id`.
+///
+/// Matched sentences are in the order of the first treebank. If a
+/// sentence in any of the treebanks does not have a `sent_id`, sentences
+/// are matched by position instead and sentences beyond the length of the
+/// shortest treebank are unmatched.
+pub fn match_sentence_ids<S>(treebanks: Vec<Vec<S>>) -> Result<SentenceMatching<S>>
+where
+    S: Borrow<Sentence>,
+{
+    let has_ids = treebanks
+        .iter()
+        .flatten()
+        .all(|sentence| sentence_id(sentence.borrow()).is_some());
+
+    if !has_ids {
+        let len = treebanks.iter().map(Vec::len).min().unwrap_or(0);
+        let unmatched = treebanks
+            .iter()
+            .map(|sentences| {
+                sentences
+                    .iter()
+                    .enumerate()
+                    .skip(len)
+                    .map(|(idx, sentence)| {
+                        sentence_id(sentence.borrow())
+                            .map(ToOwned::to_owned)
+                            .unwrap_or_else(|| (idx + 1).to_string())
+                    })
+                    .collect()
+            })
+            .collect();
+        let treebanks = treebanks
+            .into_iter()
+            .map(|mut sentences| {
+                sentences.truncate(len);
+                sentences
+            })
+            .collect();
+        return Ok(SentenceMatching {
+            treebanks,
+            unmatched,
+            by_position: true,
+        });
+    }
+
+    // Index the sentences of all treebanks by their identifiers, keeping
+    // the identifiers in treebank order.
+    let mut ids = Vec::with_capacity(treebanks.len());
+    let mut sentences_by_id = Vec::with_capacity(treebanks.len());
+    for (treebank_idx, sentences) in treebanks.into_iter().enumerate() {
+        let mut treebank_ids = Vec::with_capacity(sentences.len());
+        let mut by_id = HashMap::with_capacity(sentences.len());
+        for sentence in sentences {
+            let id = sentence_id(sentence.borrow()).unwrap().to_owned();
+            ensure!(
+                !by_id.contains_key(&id),
+                "Duplicate sent_id in treebank {}: {}",
+                treebank_idx + 1,
+                id
+            );
+            treebank_ids.push(id.clone());
+            by_id.insert(id, sentence);
+        }
+        ids.push(treebank_ids);
+        sentences_by_id.push(by_id);
+    }
+
+    let mut matched = sentences_by_id
+        .iter()
+        .map(|_| Vec::new())
+        .collect::<Vec<_>>();
+    for id in &ids[0] {
+        if sentences_by_id.iter().all(|by_id| by_id.contains_key(id)) {
+            for (sentences, by_id) in matched.iter_mut().zip(&mut sentences_by_id) {
+                sentences.push(by_id.remove(id).unwrap());
+            }
+        }
+    }
+
+    let unmatched = ids
+        .into_iter()
+        .zip(&sentences_by_id)
+        .map(|(ids, by_id)| {
+            ids.into_iter()
+                .filter(|id| by_id.contains_key(id))
+                .collect()
+        })
+        .collect();
+
+    Ok(SentenceMatching {
+        treebanks: matched,
+        unmatched,
+        by_position: false,
+    })
+}
+
+/// Iterator over the sentences of a treebank.
+pub type SentenceIter<S = Sentence> = Box<dyn Iterator<Item = Result<S, Error>>>;
+
+/// Match the sentences of treebanks by their `sent_id`.
+///
+/// The treebanks are read completely. Identifiers of sentences that
+/// cannot be matched are written to the standard error. The returned
+/// treebanks contain the matched sentences.
+pub fn match_treebanks<S>(
+    treebanks: Vec<SentenceIter<S>>,
+    names: &[&str],
+) -> Result<Vec<SentenceIter<S>>>
+where
+    S: 'static + Borrow<Sentence>,
+{
+    let treebanks = treebanks
+        .into_iter()
+        .zip(names)
+        .map(|(treebank, name)| {
+            treebank
+                .collect::<Result<Vec<_>, _>>()
+                .context(format!("Cannot read sentence from treebank: {}", name))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let matching = match_sentence_ids(treebanks)?;
+    matching.write_unmatched(&mut stderr(), names)?;
+
+    Ok(matching
+        .treebanks
+        .into_iter()
+        .map(|sentences| Box::new(sentences.into_iter().map(Ok)) as SentenceIter<S>)
+        .collect())
+}
+
+/// Alignment of two treebanks with possibly different tokenization.
+pub struct Alignment {
+    pub pairs: Vec<SentencePair>,
+    pub gold_tokens: usize,
+    pub predicted_tokens: usize,
+    pub aligned_tokens: usize,
+    pub gold_words: usize,
+    pub predicted_words: usize,
+    pub aligned_words: usize,
+    pub gold_sentences: usize,
+    pub predicted_sentences: usize,
+    pub aligned_sentences: usize,
+}
+
+/// A word with its span in the treebank text without whitespace.
+///
+/// The words of a multi-word token span all characters of the token.
+struct WordSpan {
+    sentence: usize,
+    idx: usize,
+    form: String,
+    start: usize,
+    end: usize,
+    multiword: bool,
+}
+
+/// Token, word and sentence spans of a treebank.
+struct Spans {
+    tokens: Vec<(usize, usize)>,
+    words: Vec<WordSpan>,
+    sentences: Vec<(usize, usize)>,
+    text: String,
+}
+
+impl Spans {
+    fn new(sentences: &[Words]) -> Self {
+        let mut spans = Spans {
+            tokens: Vec::new(),
+            words: Vec::new(),
+            sentences: Vec::new(),
+            text: String::new(),
+        };
+
+        for (sentence_idx, words) in sentences.iter().enumerate() {
+            let sentence = &words.sentence;
+            let sentence_start = spans.text.len();
+            let mut multiword_tokens = words.multiword_tokens.iter().peekable();
+
+            let mut idx = 1;
+            while idx < sentence.len() {
+                // Skip multi-word tokens that overlap with earlier tokens.
+                while multiword_tokens
+                    .next_if(|token| token.first < idx)
+                    .is_some()
+                {}
+
+                let (last, multiword) = match multiword_tokens.next_if(|token| token.first == idx) {
+                    Some(token) => {
+                        spans.push_token(&token.form);
+                        (token.last.min(sentence.len() - 1).max(idx), true)
+                    }
+                    None => {
+                        spans.push_token(sentence[idx].token().unwrap().form());
+                        (idx, false)
+                    }
+                };
+
+                let (start, end) = *spans.tokens.last().unwrap();
+                for idx in idx..=last {
+                    spans.words.push(WordSpan {
+                        sentence: sentence_idx,
+                        idx,
+                        form: sentence[idx].token().unwrap().form().to_lowercase(),
+                        start,
+                        end,
+                        multiword,
+                    });
+                }
+
+                idx = last + 1;
+            }
+
+            spans.sentences.push((sentence_start, spans.text.len()));
+        }
+
+        spans
+    }
+
+    /// Add a token to the text, disregarding whitespace.
+    fn push_token(&mut self, form: &str) {
+        let start = self.text.len();
+        self.text
+            .extend(form.chars().filter(|c| !c.is_whitespace()));
+        self.tokens.push((start, self.text.len()));
+    }
+}
+
+/// Align two treebanks using character offsets.
+///
+/// Following the CoNLL 2018 shared task, tokens are aligned when they
+/// span the same characters of the treebank text, disregarding
+/// whitespace. The text of both treebanks must be identical. Words of
+/// multi-word tokens are aligned using the longest common subsequence of
+/// the (lowercased) word forms that overlap with the multi-word tokens.
+///
+/// Each gold sentence is paired with a predicted sentence that consists
+/// of the aligned predicted tokens. Heads of aligned tokens are mapped
+/// to gold token indices. Predicted heads that are not aligned or that
+/// are in a different gold sentence cannot be mapped. The corresponding
+/// tokens are attached to themselves, so that their relations are still
+/// counted as predictions, but their heads are never correct. Predicted
+/// words that are not aligned are added to the pair of the gold sentence
+/// in which they start.
+pub fn align_treebanks(
+    gold: impl IntoIterator<Item = Result<Words, Error>>,
+    predicted: impl IntoIterator<Item = Result<Words, Error>>,
+) -> Result<Alignment> {
+    let gold = gold
+        .into_iter()
+        .map(|s| s.context("Cannot read sentence from gold treebank"))
+        .collect::<Result<Vec<_>>>()?;
+    let predicted = predicted
+        .into_iter()
+        .map(|s| s.context("Cannot read sentence from predicted treebank"))
+        .collect::<Result<Vec<_>>>()?;
+
+    let gold_spans = Spans::new(&gold);
+    let predicted_spans = Spans::new(&predicted);
+
+    if gold_spans.text != predicted_spans.text {
+        let offset = gold_spans
+            .text
+            .chars()
+            .zip(predicted_spans.text.chars())
+            .take_while(|(g, p)| g == p)
+            .count();
+        bail!(
+            "The text of the gold and predicted treebanks differs at character {}",
+            offset
+        );
+    }
+
+    let word_alignments = align_words(&gold_spans.words, &predicted_spans.words);
+
+    let alignment = Alignment {
+        pairs: Vec::new(),
+        gold_tokens: gold_spans.tokens.len(),
+        predicted_tokens: predicted_spans.tokens.len(),
+        aligned_tokens: count_common_spans(&gold_spans.tokens, &predicted_spans.tokens),
+        gold_words: gold_spans.words.len(),
+        predicted_words: predicted_spans.words.len(),
+        aligned_words: word_alignments.len(),
+        gold_sentences: gold_spans.sentences.len(),
+        predicted_sentences: predicted_spans.sentences.len(),
+        aligned_sentences: count_common_spans(&gold_spans.sentences, &predicted_spans.sentences),
+    };
+
+    let unaligned = unaligned_predicted(
+        &gold_spans.sentences,
+        &predicted_spans.words,
+        &predicted,
+        &word_alignments,
+    );
+
+    Ok(Alignment {
+        pairs: project_predicted(gold, &predicted, &word_alignments, unaligned),
+        ..alignment
+    })
+}
+
+/// Predicted words and empty nodes that are not aligned.
+#[derive(Default)]
+struct Unaligned {
+    words: Vec<UnalignedWord>,
+    empty_nodes: Vec<EmptyNode>,
+}
+
+/// Get the predicted words that are not aligned to a gold word.
+///
+/// The words are grouped by the gold sentence in which they start. Empty
+/// nodes are unaligned when the word that they follow is unaligned.
+fn unaligned_predicted(
+    gold_sentences: &[(usize, usize)],
+    predicted_words: &[WordSpan],
+    predicted: &[Words],
+    word_alignments: &HashMap<(usize, usize), (usize, usize)>,
+) -> Vec<Unaligned> {
+    let mut unaligned = gold_sentences
+        .iter()
+        .map(|_| Unaligned::default())
+        .collect::<Vec<_>>();
+
+    for word in predicted_words {
+        if word_alignments.contains_key(&(word.sentence, word.idx)) {
+            continue;
+        }
+
+        let gold_sentence = gold_sentences
+            .partition_point(|&(start, _)| start <= word.start)
+            .saturating_sub(1);
+        let unaligned = match unaligned.get_mut(gold_sentence) {
+            Some(unaligned) => unaligned,
+            None => continue,
+        };
+
+        let words = &predicted[word.sentence];
+        let sentence = &words.sentence;
+        unaligned.words.push(UnalignedWord {
+            token: sentence[word.idx].token().unwrap().clone(),
+            relation: sentence
+                .dep_graph()
+                .head(word.idx)
+                .map(|triple| triple.relation().unwrap_or("_").to_owned()),
+        });
+        unaligned.empty_nodes.extend(
+            words
+                .empty_nodes
+                .iter()
+                .filter(|node| empty_node_anchor(node) == word.idx)
+                .cloned(),
+        );
+    }
+
+    unaligned
+}
+
+/// Get the word by which an empty node is aligned.
+///
+/// This is the word that the empty node follows, or the first word for
+/// empty nodes before the first word.
+fn empty_node_anchor(node: &EmptyNode) -> usize {
+    node.word.max(1)
+}
+
+/// Align the words of two treebanks.
+///
+/// Returns a mapping from (predicted sentence, predicted index) to (gold
+/// sentence, gold index).
+fn align_words(
+    gold: &[WordSpan],
+    predicted: &[WordSpan],
+) -> HashMap<(usize, usize), (usize, usize)> {
+    let mut word_alignments = HashMap::new();
+
+    let (mut gold_idx, mut predicted_idx) = (0, 0);
+    while gold_idx < gold.len() && predicted_idx < predicted.len() {
+        let gold_word = &gold[gold_idx];
+        let predicted_word = &predicted[predicted_idx];
+
+        if gold_word.multiword || predicted_word.multiword {
+            let (gold_region, predicted_region) =
+                multiword_region(gold, predicted, gold_idx, predicted_idx);
+
+            // Multi-word tokens without any characters do not form a region.
+            if gold_region.end == gold_idx && predicted_region.end == predicted_idx {
+                gold_idx += 1;
+                predicted_idx += 1;
+                continue;
+            }
+
+            align_lcs(
+                &gold[gold_region.clone()],
+                &predicted[predicted_region.clone()],
+                &mut word_alignments,
+            );
+
+            gold_idx = gold_region.end;
+            predicted_idx = predicted_region.end;
+            continue;
+        }
+
+        if (gold_word.start, gold_word.end) == (predicted_word.start, predicted_word.end) {
+            word_alignments.insert(
+                (predicted_word.sentence, predicted_word.idx),
+                (gold_word.sentence, gold_word.idx),
+            );
+        }
+
+        if gold_word.end <= predicted_word.end {
+            gold_idx += 1;
+        }
+        if predicted_word.end <= gold_word.end {
+            predicted_idx += 1;
+        }
+    }
+
+    word_alignments
+}
+
+/// Find the gold and predicted words that overlap with multi-word tokens.
+///
+/// One of the words at `gold_idx` and `predicted_idx` must be part of a
+/// multi-word token. The region is extended until both treebanks are
+/// beyond all overlapping multi-word tokens.
+fn multiword_region(
+    gold: &[WordSpan],
+    predicted: &[WordSpan],
+    mut gold_idx: usize,
+    mut predicted_idx: usize,
+) -> (Range<usize>, Range<usize>) {
+    // Skip a word that starts before the multi-word token.
+    let mut end = if gold[gold_idx].multiword {
+        if !predicted[predicted_idx].multiword
+            && predicted[predicted_idx].start < gold[gold_idx].start
+        {
+            predicted_idx += 1;
+        }
+        gold[gold_idx].end
+    } else {
+        if gold[gold_idx].start < predicted[predicted_idx].start {
+            gold_idx += 1;
+        }
+        predicted[predicted_idx].end
+    };
+
+    let (gold_start, predicted_start) = (gold_idx, predicted_idx);
+    while !beyond_end(gold, gold_idx, end) || !beyond_end(predicted, predicted_idx, end) {
+        let word = if gold_idx < gold.len()
+            && (predicted_idx >= predicted.len()
+                || gold[gold_idx].start <= predicted[predicted_idx].start)
+        {
+            gold_idx += 1;
+            &gold[gold_idx - 1]
+        } else {
+            predicted_idx += 1;
+            &predicted[predicted_idx - 1]
+        };
+
+        if word.multiword {
+            end = end.max(word.end);
+        }
+    }
+
+    (gold_start..gold_idx, predicted_start..predicted_idx)
+}
+
+/// Check whether the word at `idx` is beyond the end of a region.
+fn beyond_end(words: &[WordSpan], idx: usize, end: usize) -> bool {
+    match words.get(idx) {
+        None => true,
+        Some(word) if word.multiword => word.start >= end,
+        Some(word) => word.end > end,
+    }
+}
+
+/// Align words using the longest common subsequence of their forms.
+fn align_lcs(
+    gold: &[WordSpan],
+    predicted: &[WordSpan],
+    word_alignments: &mut HashMap<(usize, usize), (usize, usize)>,
+) {
+    // lcs[i][j] is the length of the longest common subsequence of
+    // gold[i..] and predicted[j..].
+    let mut lcs = vec![vec![0; predicted.len() + 1]; gold.len() + 1];
+    for i in (0..gold.len()).rev() {
+        for j in (0..predicted.len()).rev() {
+            lcs[i][j] = if gold[i].form == predicted[j].form {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < gold.len() && j < predicted.len() {
+        if gold[i].form == predicted[j].form {
+            word_alignments.insert(
+                (predicted[j].sentence, predicted[j].idx),
+                (gold[i].sentence, gold[i].idx),
+            );
+            i += 1;
+            j += 1;
+        } else if lcs[i][j] == lcs[i + 1][j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+}
+
+/// Count the spans that occur in both lists of consecutive spans.
+fn count_common_spans(gold: &[(usize, usize)], predicted: &[(usize, usize)]) -> usize {
+    let mut common = 0;
+
+    let (mut gold_idx, mut predicted_idx) = (0, 0);
+    while gold_idx < gold.len() && predicted_idx < predicted.len() {
+        let (gold_span, predicted_span) = (gold[gold_idx], predicted[predicted_idx]);
+
+        if gold_span == predicted_span {
+            common += 1;
+        }
+
+        if gold_span.1 <= predicted_span.1 {
+            gold_idx += 1;
+        }
+        if predicted_span.1 <= gold_span.1 {
+            predicted_idx += 1;
+        }
+    }
+
+    common
+}
+
+/// Project the aligned predicted tokens and relations on the gold sentences.
+fn project_predicted(
+    gold: Vec<Words>,
+    predicted: &[Words],
+    word_alignments: &HashMap<(usize, usize), (usize, usize)>,
+    unaligned: Vec<Unaligned>,
+) -> Vec<SentencePair> {
+    let mut projected = gold
+        .iter()
+        .map(|words| {
+            let mut projected = Sentence::new();
+            for _ in 1..words.sentence.len() {
+                projected.push(Token::new(""));
+            }
+            projected
+        })
+        .collect::<Vec<_>>();
+    let mut aligned = gold
+        .iter()
+        .map(|words| vec![false; words.sentence.len()])
+        .collect::<Vec<_>>();
+    let mut projected_empty_nodes = gold.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+
+    for (&(predicted_sentence, predicted_idx), &(gold_sentence, gold_idx)) in word_alignments {
+        let map_word =
+            |word| map_predicted_word(word_alignments, predicted_sentence, gold_sentence, word);
+        let predicted = &predicted[predicted_sentence];
+
+        let mut token = predicted.sentence[predicted_idx].token().unwrap().clone();
+        if let Some(deps) = token.deps() {
+            let deps = project_deps(deps, &gold_idx.to_string(), map_word);
+            token.set_deps(Some(deps));
+        }
+
+        aligned[gold_sentence][gold_idx] = true;
+        projected[gold_sentence][gold_idx] = Node::Token(token);
+
+        for node in &predicted.empty_nodes {
+            if empty_node_anchor(node) != predicted_idx {
+                continue;
+            }
+
+            let mut node = node.clone();
+            node.word = if node.word == 0 { 0 } else { gold_idx };
+            let id = node.id();
+            node.deps = node.deps.map(|deps| project_deps(&deps, &id, map_word));
+            projected_empty_nodes[gold_sentence].push(node);
+        }
+
+        let triple = match predicted.sentence.dep_graph().head(predicted_idx) {
+            Some(triple) => triple,
+            None => continue,
+        };
+
+        // Attach the token to itself when its head cannot be mapped.
+        projected[gold_sentence]
+            .dep_graph_mut()
+            .add_deprel(DepTriple::new(
+                map_word(triple.head()).unwrap_or(gold_idx),
+                triple.relation().map(ToOwned::to_owned),
+                gold_idx,
+            ))
+            .expect("Cannot add projected relation");
+    }
+
+    gold.into_iter()
+        .zip(projected)
+        .zip(aligned)
+        .zip(projected_empty_nodes)
+        .zip(unaligned)
+        .map(|((((gold, predicted), aligned), empty_nodes), unaligned)| {
+            SentencePair::from_parts(
+                gold.sentence,
+                predicted,
+                aligned,
+                unaligned.words,
+                EmptyNodes {
+                    gold: gold.empty_nodes,
+                    predicted: empty_nodes,
+                    unaligned: unaligned.empty_nodes,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Map a word of a predicted sentence to a word of a gold sentence.
+///
+/// Returns `None` when the word is not aligned to a word of the gold
+/// sentence. The root is always mapped to the root.
+fn map_predicted_word(
+    word_alignments: &HashMap<(usize, usize), (usize, usize)>,
+    predicted_sentence: usize,
+    gold_sentence: usize,
+    word: usize,
+) -> Option<usize> {
+    if word == 0 {
+        return Some(0);
+    }
+
+    match word_alignments.get(&(predicted_sentence, word)) {
+        Some(&(head_sentence, head_idx)) if head_sentence == gold_sentence => Some(head_idx),
+        _ => None,
+    }
+}
+
+/// Map the heads of enhanced dependencies (the DEPS column).
+///
+/// Heads of empty nodes (such as `5.1`) are mapped using the word that
+/// the empty node follows. Like basic heads that cannot be mapped, heads
+/// that cannot be mapped are replaced by the dependent. Dependencies that
+/// cannot be parsed are left as-is.
+fn project_deps(deps: &str, dependent: &str, map_word: impl Fn(usize) -> Option<usize>) -> String {
+    let map_head = |head: &str| match head.split_once('.') {
+        Some((word, position)) => Some(format!("{}.{}", map_word(word.parse().ok()?)?, position)),
+        None => map_word(head.parse().ok()?).map(|word| word.to_string()),
+    };
+
+    deps.split('|')
+        .map(|dep| match dep.split_once(':') {
+            Some((head, relation)) => {
+                let head = map_head(head).unwrap_or_else(|| dependent.to_owned());
+                format!("{}:{}", head, relation)
+            }
+            None => dep.to_owned(),
+        })
+        .join("|")
+}
+
+#[cfg(test)]
+mod tests {
+    use conllu::Error;
+    use udgraph::graph::Sentence;
+
+    use super::{align_treebanks, Alignment};
+    use crate::io::{WordReader, Words};
+
+    fn words(data: &str) -> impl Iterator<Item = Result<Words, Error>> + '_ {
+        WordReader::new(data.as_bytes()).words()
+    }
+
+    fn align(gold: &str, predicted: &str) -> Alignment {
+        align_treebanks(words(gold), words(predicted)).unwrap()
+    }
+
+    fn head(sentence: &Sentence, idx: usize) -> Option<(usize, Option<String>)> {
+        sentence
+            .dep_graph()
+            .head(idx)
+            .map(|triple| (triple.head(), triple.relation().map(ToOwned::to_owned)))
+    }
+
+    static I_CANT_GO: &str = "1\tI\t_\t_\t_\t_\t2\tnsubj\t_\t_
+2\tcan't\t_\t_\t_\t_\t0\troot\t_\t_
+3\tgo\t_\t_\t_\t_\t2\txcomp\t_\t_
+
+";
+
+    #[test]
+    fn identical_tokenization() {
+        let alignment = align(I_CANT_GO, I_CANT_GO);
+
+        assert_eq!(alignment.gold_tokens, 3);
+        assert_eq!(alignment.predicted_tokens, 3);
+        assert_eq!(alignment.aligned_tokens, 3);
+        assert_eq!(alignment.aligned_words, 3);
+        assert_eq!(alignment.aligned_sentences, 1);
+
+        let pair = &alignment.pairs[0];
+        assert!(pair.is_fully_aligned());
+        assert_eq!(pair.aligned_indices().collect::<Vec<_>>(), vec![1, 2, 3]);
+        for idx in 1..4 {
+            assert_eq!(head(&pair.predicted, idx), head(&pair.gold, idx));
+        }
+    }
+
+    #[test]
+    fn different_tokenization() {
+        let predicted = "1\tI\t_\t_\t_\t_\t3\tnsubj\t_\t_
+2\tca\t_\t_\t_\t_\t4\taux\t_\t_
+3\tn't\t_\t_\t_\t_\t4\tadvmod\t_\t_
+4\tgo\t_\t_\t_\t_\t0\troot\t_\t_
+
+";
+        let alignment = align(I_CANT_GO, predicted);
+
+        assert_eq!(alignment.gold_tokens, 3);
+        assert_eq!(alignment.predicted_tokens, 4);
+        assert_eq!(alignment.aligned_tokens, 2);
+        assert_eq!(alignment.gold_words, 3);
+        assert_eq!(alignment.predicted_words, 4);
+        assert_eq!(alignment.aligned_words, 2);
+
+        let pair = &alignment.pairs[0];
+        assert!(!pair.is_fully_aligned());
+        assert_eq!(pair.aligned_indices().collect::<Vec<_>>(), vec![1, 3]);
+        assert!(!pair.is_aligned(2));
+
+        // The head of "I" is not aligned, so it is attached to itself, but
+        // its relation is kept.
+        assert_eq!(
+            head(&pair.predicted, 1),
+            Some((1, Some("nsubj".to_owned())))
+        );
+        assert_eq!(head(&pair.predicted, 3), Some((0, Some("root".to_owned()))));
+
+        assert_eq!(pair.unaligned_indices().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            pair.unaligned_predicted()
+                .map(|word| (word.token.form(), word.relation.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("ca", Some("aux")), ("n't", Some("advmod"))]
+        );
+    }
+
+    static DEL_PERRO: &str = "1-2\tdel\t_\t_\t_\t_\t_\t_\t_\t_
+1\tde\t_\t_\t_\t_\t3\tcase\t_\t_
+2\tel\t_\t_\t_\t_\t3\tdet\t_\t_
+3\tperro\t_\t_\t_\t_\t0\troot\t_\t_
+
+";
+
+    #[test]
+    fn multiword_tokens() {
+        let alignment = align(DEL_PERRO, DEL_PERRO);
+
+        assert_eq!(alignment.gold_tokens, 2);
+        assert_eq!(alignment.aligned_tokens, 2);
+        assert_eq!(alignment.gold_words, 3);
+        assert_eq!(alignment.aligned_words, 3);
+
+        let pair = &alignment.pairs[0];
+        assert_eq!(pair.aligned_indices().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(head(&pair.predicted, 2), Some((3, Some("det".to_owned()))));
+    }
+
+    #[test]
+    fn multiword_tokens_aligned_by_form() {
+        let predicted = "1-2\tdel\t_\t_\t_\t_\t_\t_\t_\t_
+1\tdel\t_\t_\t_\t_\t3\tcase\t_\t_
+2\tEl\t_\t_\t_\t_\t3\tdet\t_\t_
+3\tperro\t_\t_\t_\t_\t0\troot\t_\t_
+
+";
+        let alignment = align(DEL_PERRO, predicted);
+
+        assert_eq!(alignment.aligned_tokens, 2);
+        assert_eq!(alignment.aligned_words, 2);
+
+        let pair = &alignment.pairs[0];
+        assert_eq!(pair.aligned_indices().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn unsplit_multiword_token() {
+        let predicted = "1\tdel\t_\t_\t_\t_\t2\tdet\t_\t_
+2\tperro\t_\t_\t_\t_\t0\troot\t_\t_
+
+";
+        let alignment = align(DEL_PERRO, predicted);
+
+        assert_eq!(alignment.gold_tokens, 2);
+        assert_eq!(alignment.predicted_tokens, 2);
+        assert_eq!(alignment.aligned_tokens, 2);
+        assert_eq!(alignment.gold_words, 3);
+        assert_eq!(alignment.predicted_words, 2);
+        assert_eq!(alignment.aligned_words, 1);
+
+        let pair = &alignment.pairs[0];
+        assert_eq!(pair.aligned_indices().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn sentence_segmentation() {
+        let gold = "1\tHi\t_\t_\t_\t_\t0\troot\t_\t_
+2\t!\t_\t_\t_\t_\t1\tpunct\t_\t_
+
+1\tBye\t_\t_\t_\t_\t0\troot\t_\t_
+
+";
+        let predicted = "1\tHi\t_\t_\t_\t_\t0\troot\t_\t_
+2\t!\t_\t_\t_\t_\t3\tpunct\t_\t_
+3\tBye\t_\t_\t_\t_\t1\tparataxis\t_\t_
+
+";
+        let alignment = align(gold, predicted);
+
+        assert_eq!(alignment.gold_sentences, 2);
+        assert_eq!(alignment.predicted_sentences, 1);
+        assert_eq!(alignment.aligned_sentences, 0);
+        assert_eq!(alignment.aligned_words, 3);
+
+        // Heads in another gold sentence cannot be mapped.
+        assert_eq!(
+            head(&alignment.pairs[0].predicted, 2),
+            Some((2, Some("punct".to_owned())))
+        );
+        assert_eq!(
+            head(&alignment.pairs[1].predicted, 1),
+            Some((1, Some("parataxis".to_owned())))
+        );
+    }
+
+    #[test]
+    fn enhanced_dependencies() {
+        let gold = "1\tI\t_\t_\t_\t_\t2\tnsubj\t2:nsubj|2.1:nsubj\t_
+2\tcan't\t_\t_\t_\t_\t0\troot\t0:root\t_
+2.1\tgo\t_\t_\t_\t_\t_\t_\t2:xcomp\t_
+3\tgo\t_\t_\t_\t_\t2\txcomp\t2:xcomp\t_
+
+";
+        let predicted = "1\tI\t_\t_\t_\t_\t4\tnsubj\t4:nsubj|3.1:nsubj\t_
+2\tca\t_\t_\t_\t_\t4\taux\t4:aux\t_
+3\tn't\t_\t_\t_\t_\t4\tadvmod\t4:advmod\t_
+3.1\tgo\t_\t_\t_\t_\t_\t_\t4:xcomp\t_
+4\tgo\t_\t_\t_\t_\t0\troot\t0:root\t_
+
+";
+        let alignment = align(gold, predicted);
+        let pair = &alignment.pairs[0];
+
+        // Empty nodes are not words.
+        assert_eq!(pair.gold.len(), 4);
+
+        // The predicted empty node follows an unaligned word, so it cannot
+        // be mapped, nor can the heads that refer to it.
+        let empty_nodes = pair.empty_nodes().unwrap();
+        assert_eq!(empty_nodes.gold[0].id(), "2.1");
+        assert!(empty_nodes.predicted.is_empty());
+        assert_eq!(empty_nodes.unaligned[0].id(), "3.1");
+
+        let deps = |idx| pair.predicted[idx].token().unwrap().deps();
+        assert_eq!(deps(1), Some("3:nsubj|1:nsubj"));
+        assert_eq!(deps(3), Some("0:root"));
+    }
+
+    #[test]
+    fn empty_nodes_are_renumbered() {
+        let gold = "1\tI\t_\t_\t_\t_\t2\tnsubj\t3.1:nsubj\t_
+2\tcan't\t_\t_\t_\t_\t0\troot\t0:root\t_
+3\tgo\t_\t_\t_\t_\t2\txcomp\t2:xcomp\t_
+3.1\twent\t_\t_\t_\t_\t_\t_\t3:conj\t_
+
+";
+        let predicted = "1\tI\t_\t_\t_\t_\t4\tnsubj\t4.1:nsubj\t_
+2\tca\t_\t_\t_\t_\t4\taux\t4:aux\t_
+3\tn't\t_\t_\t_\t_\t4\tadvmod\t4:advmod\t_
+4\tgo\t_\t_\t_\t_\t0\troot\t0:root\t_
+4.1\twent\t_\t_\t_\t_\t_\t_\t4:conj\t_
+
+";
+        let alignment = align(gold, predicted);
+        let pair = &alignment.pairs[0];
+
+        let empty_nodes = pair.empty_nodes().unwrap();
+        assert_eq!(empty_nodes.predicted.len(), 1);
+        assert_eq!(empty_nodes.predicted[0].id(), "3.1");
+        assert_eq!(empty_nodes.predicted[0].deps.as_deref(), Some("3:conj"));
+
+        assert_eq!(pair.predicted[1].token().unwrap().deps(), Some("3.1:nsubj"));
+    }
+
+    #[test]
+    fn different_text() {
+        let predicted = "1\tI\t_\t_\t_\t_\t0\troot\t_\t_
+2\tcannot\t_\t_\t_\t_\t1\tdep\t_\t_
+
+";
+        assert!(align_treebanks(words(I_CANT_GO), words(predicted)).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use udgraph::graph::Sentence;
+
+use crate::html::escape;
+
+/// A dependency arc with the level at which it is drawn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Arc {
+    pub dependent: usize,
+    pub head: usize,
+    pub level: usize,
+}
+
+impl Arc {
+    /// Get the first and last token of the arc.
+    pub fn span(&self) -> (usize, usize) {
+        (self.dependent.min(self.head), self.dependent.max(self.head))
+    }
+
+    fn overlaps(&self, other: &Arc) -> bool {
+        let (start, end) = self.span();
+        let (other_start, other_end) = other.span();
+        start <= other_end && other_start <= end
+    }
+}
+
+/// Get the arcs of a sentence with their drawing levels.
+///
+/// An arc is drawn at a higher level than all shorter arcs that it
+/// overlaps with, so that an arc encloses the arcs within its span.
+/// Attachments to the root and tokens attached to themselves are not
+/// included.
+pub fn arc_levels(sentence: &Sentence) -> Vec<Arc> {
+    let dep_graph = sentence.dep_graph();
+
+    let mut arcs = (1..sentence.len())
+        .filter_map(|dependent| dep_graph.head(dependent))
+        .filter(|triple| triple.head() != 0 && triple.head() != triple.dependent())
+        .map(|triple| Arc {
+            dependent: triple.dependent(),
+            head: triple.head(),
+            level: 0,
+        })
+        .collect::<Vec<_>>();
+    arcs.sort_by_key(|arc| {
+        let (start, end) = arc.span();
+        (end - start, start)
+    });
+
+    for idx in 0..arcs.len() {
+        arcs[idx].level = arcs[..idx]
+            .iter()
+            .filter(|arc| arc.overlaps(&arcs[idx]))
+            .map(|arc| arc.level + 1)
+            .max()
+            .unwrap_or(0);
+    }
+
+    arcs
+}
+
+/// Render the arcs of a sentence as an ASCII diagram.
+///
+/// One line is returned per token (excluding the root). All lines have
+/// the same width, so that the token text can be appended to them.
+pub fn ascii_arcs(sentence: &Sentence) -> Vec<String> {
+    let arcs = arc_levels(sentence);
+    let n_levels = arcs.iter().map(|arc| arc.level + 1).max().unwrap_or(0);
+    let width = 2 * n_levels + 1;
+
+    let mut grid = vec![vec![' '; width]; sentence.len()];
+
+    // Draw the outermost arcs first, so that the corners of inner arcs
+    // are not overwritten by horizontal lines.
+    for arc in arcs.iter().rev() {
+        let (start, end) = arc.span();
+        let column = width - 3 - 2 * arc.level;
+
+        for row in &mut grid[start + 1..end] {
+            row[column] = '|';
+        }
+
+        for &row in &[start, end] {
+            grid[row][column] = '+';
+            for cell in &mut grid[row][column + 1..width - 1] {
+                if *cell == ' ' {
+                    *cell = '-';
+                }
+            }
+            if grid[row][width - 1] == ' ' {
+                grid[row][width - 1] = '-';
+            }
+        }
+
+        grid[arc.dependent][width - 1] = '>';
+    }
+
+    grid.into_iter()
+        .skip(1)
+        .map(|row| row.into_iter().collect())
+        .collect()
+}
+
+/// Render a sentence with its arcs as an SVG image.
+///
+/// Tokens for which `highlight` returns true are drawn with the `diff`
+/// class, as are the arcs that attach them.
+pub fn svg_arcs(sentence: &Sentence, highlight: impl Fn(usize) -> bool) -> String {
+    const CHAR_WIDTH: usize = 8;
+    const LEVEL_HEIGHT: usize = 20;
+    const TOKEN_MARGIN: usize = 16;
+
+    let dep_graph = sentence.dep_graph();
+    let relation = |idx| {
+        dep_graph
+            .head(idx)
+            .and_then(|triple| triple.relation().map(ToOwned::to_owned))
+            .unwrap_or_else(|| "_".to_owned())
+    };
+
+    let arcs = arc_levels(sentence);
+    let n_levels = arcs.iter().map(|arc| arc.level + 1).max().unwrap_or(0);
+
+    // The root is drawn as an arc one level above all other arcs.
+    let baseline = (n_levels + 2) * LEVEL_HEIGHT + 10;
+
+    // Horizontal centers of the tokens.
+    let mut centers = vec![0; sentence.len()];
+    let mut x = 0;
+    for (idx, center) in centers.iter_mut().enumerate().skip(1) {
+        let form = sentence[idx]
+            .token()
+            .map(|token| token.form())
+            .unwrap_or("_");
+        let width = form.chars().count() * CHAR_WIDTH + TOKEN_MARGIN;
+        *center = x + width / 2;
+        x += width;
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        x,
+        baseline + 10
+    );
+
+    for arc in &arcs {
+        let class = if highlight(arc.dependent) {
+            "arc diff"
+        } else {
+            "arc"
+        };
+        let (x_head, x_dependent) = (centers[arc.head], centers[arc.dependent]);
+        let y = baseline - 15;
+        let top = y - (arc.level + 1) * LEVEL_HEIGHT;
+        svg.push_str(&format!(
+            "<path class=\"{}\" d=\"M {} {} C {} {}, {} {}, {} {}\"/>\n",
+            class, x_head, y, x_head, top, x_dependent, top, x_dependent, y
+        ));
+        svg.push_str(&arrow_head(x_dependent, y, highlight(arc.dependent)));
+        svg.push_str(&format!(
+            "<text class=\"label{}\" x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+            if highlight(arc.dependent) {
+                " diff"
+            } else {
+                ""
+            },
+            (x_head + x_dependent) / 2,
+            y - 3 * (y - top) / 4 - 2,
+            escape(&relation(arc.dependent))
+        ));
+    }
+
+    for idx in 1..sentence.len() {
+        let class = if highlight(idx) {
+            " class=\"diff\""
+        } else {
+            ""
+        };
+
+        if let Some(triple) = dep_graph.head(idx) {
+            if triple.head() == 0 {
+                let class = if highlight(idx) { "arc diff" } else { "arc" };
+                let y = baseline - 15;
+                svg.push_str(&format!(
+                    "<path class=\"{}\" d=\"M {} {} L {} {}\"/>\n",
+                    class, centers[idx], LEVEL_HEIGHT, centers[idx], y
+                ));
+                svg.push_str(&arrow_head(centers[idx], y, highlight(idx)));
+                svg.push_str(&format!(
+                    "<text class=\"label{}\" x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                    if highlight(idx) { " diff" } else { "" },
+                    centers[idx],
+                    LEVEL_HEIGHT - 4,
+                    escape(&relation(idx))
+                ));
+            }
+        }
+
+        let form = sentence[idx]
+            .token()
+            .map(|token| token.form())
+            .unwrap_or("_");
+        svg.push_str(&format!(
+            "<text{} x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+            class,
+            centers[idx],
+            baseline,
+            escape(form)
+        ));
+    }
+
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+/// Draw an arrow head pointing down at the given position.
+fn arrow_head(x: usize, y: usize, highlight: bool) -> String {
+    format!(
+        "<path class=\"{}\" d=\"M {} {} L {} {} L {} {} Z\"/>\n",
+        if highlight { "head diff" } else { "head" },
+        x,
+        y,
+        x - 3,
+        y - 6,
+        x + 3,
+        y - 6
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use conllu::io::{ReadSentence, Reader};
+    use udgraph::graph::Sentence;
+
+    use super::{arc_levels, ascii_arcs, svg_arcs, Arc};
+
+    fn sentence(data: &str) -> Sentence {
+        Reader::new(data.as_bytes())
+            .read_sentence()
+            .unwrap()
+            .unwrap()
+    }
+
+    static SELF_LOOP: &str = "1\tI\t_\t_\t_\t_\t3\tnsubj\t_\t_
+2\tcan\t_\t_\t_\t_\t2\taux\t_\t_
+3\tgo\t_\t_\t_\t_\t0\troot\t_\t_
+
+";
+
+    #[test]
+    fn self_loops_are_not_drawn() {
+        let sentence = sentence(SELF_LOOP);
+
+        assert_eq!(
+            arc_levels(&sentence),
+            vec![Arc {
+                dependent: 1,
+                head: 3,
+                level: 0
+            }]
+        );
+        assert_eq!(ascii_arcs(&sentence), vec!["+->", "|  ", "+--"]);
+        assert!(svg_arcs(&sentence, |_| false).contains(">can</text>"));
+    }
+}
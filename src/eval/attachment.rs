@@ -0,0 +1,424 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::{format_err, Result};
+use conllu::display::ConlluSentence;
+use itertools::Itertools;
+use udgraph::graph::Sentence;
+
+use super::{universal_relation, Evaluator, F1Counts, PunctuationPolicy};
+use crate::align::SentencePair;
+use crate::layer::universal_features;
+use crate::report::{write_records, OutputFormat, Record};
+
+/// Universal relations of content words (CoNLL 2018 shared task).
+static CONTENT_RELATIONS: &[&str] = &[
+    "acl",
+    "advcl",
+    "advmod",
+    "amod",
+    "appos",
+    "ccomp",
+    "compound",
+    "conj",
+    "csubj",
+    "dep",
+    "discourse",
+    "dislocated",
+    "expl",
+    "fixed",
+    "flat",
+    "goeswith",
+    "iobj",
+    "list",
+    "nmod",
+    "nsubj",
+    "nummod",
+    "obj",
+    "obl",
+    "orphan",
+    "parataxis",
+    "reparandum",
+    "root",
+    "vocative",
+    "xcomp",
+];
+
+/// Universal relations of function words (CoNLL 2018 shared task).
+static FUNCTION_RELATIONS: &[&str] = &["aux", "case", "cc", "clf", "cop", "det", "mark"];
+
+/// Labeled and unlabeled attachment scores.
+#[derive(Default)]
+pub struct AttachmentEvaluator {
+    exact_match: bool,
+    per_relation: bool,
+    punctuation: PunctuationPolicy,
+    ud_metrics: bool,
+    universal_relations: bool,
+
+    relation_counts: BTreeMap<String, F1Counts>,
+    ud_metric_counts: UdMetricCounts,
+
+    labeled_correct: usize,
+    unlabeled_correct: usize,
+    total: usize,
+
+    nopunct_labeled_correct: usize,
+    nopunct_unlabeled_correct: usize,
+    nopunct_total: usize,
+
+    universal_labeled_correct: usize,
+    nopunct_universal_labeled_correct: usize,
+
+    labeled_correct_sentences: usize,
+    unlabeled_correct_sentences: usize,
+    sentences: usize,
+}
+
+impl AttachmentEvaluator {
+    /// Construct an attachment score evaluator.
+    ///
+    /// If `per_relation` is true, precision, recall and F1 are reported
+    /// per dependency relation. If `ud_metrics` is true, the CLAS, MLAS
+    /// and BLEX metrics are reported.
+    pub fn new(per_relation: bool, ud_metrics: bool) -> Self {
+        AttachmentEvaluator {
+            per_relation,
+            ud_metrics,
+            ..Default::default()
+        }
+    }
+
+    /// Report the percentage of sentences in which all tokens are
+    /// correctly attached (complete match).
+    pub fn exact_match(mut self, exact_match: bool) -> Self {
+        self.exact_match = exact_match;
+        self
+    }
+
+    /// Also report labeled attachment scores in which only the universal
+    /// part of relations is compared, ignoring subtypes such as `tmod`
+    /// in `obl:tmod`.
+    pub fn universal_relations(mut self, universal_relations: bool) -> Self {
+        self.universal_relations = universal_relations;
+        self
+    }
+
+    /// Set the policy for excluding punctuation from the `np` scores.
+    pub fn punctuation(mut self, punctuation: PunctuationPolicy) -> Self {
+        self.punctuation = punctuation;
+        self
+    }
+}
+
+impl Evaluator for AttachmentEvaluator {
+    fn process(&mut self, pair: &SentencePair) -> Result<()> {
+        let (sent1, sent2) = (&pair.gold, &pair.predicted);
+
+        let mut labeled_sentence_correct = pair.is_fully_aligned();
+        let mut unlabeled_sentence_correct = pair.is_fully_aligned();
+
+        for idx in pair.aligned_indices() {
+            let is_punct = self.punctuation.is_punctuation(sent1, idx);
+
+            self.total += 1;
+            if !is_punct {
+                self.nopunct_total += 1;
+            }
+
+            let gold_triple = sent1.dep_graph().head(idx).ok_or_else(|| {
+                format_err!(
+                    "Token without head: {} in:\n{}",
+                    idx,
+                    ConlluSentence::borrowed(sent1)
+                )
+            })?;
+
+            // Predicted tokens without a head are counted as incorrect.
+            let predicted_triple = sent2.dep_graph().head(idx);
+
+            let gold_relation = gold_triple.relation().unwrap_or("_");
+            self.relation_counts
+                .entry(gold_relation.to_owned())
+                .or_default()
+                .gold += 1;
+            if let Some(predicted_triple) = &predicted_triple {
+                self.relation_counts
+                    .entry(predicted_triple.relation().unwrap_or("_").to_owned())
+                    .or_default()
+                    .predicted += 1;
+            }
+
+            if predicted_triple.as_ref() == Some(&gold_triple) {
+                self.labeled_correct += 1;
+                self.relation_counts
+                    .get_mut(gold_relation)
+                    .expect("Missing relation counts")
+                    .correct += 1;
+
+                if !is_punct {
+                    self.nopunct_labeled_correct += 1;
+                }
+            } else {
+                labeled_sentence_correct = false;
+            }
+
+            if predicted_triple.as_ref().map(|t| t.head()) == Some(gold_triple.head()) {
+                self.unlabeled_correct += 1;
+
+                if predicted_triple
+                    .as_ref()
+                    .and_then(|t| t.relation())
+                    .map(universal_relation)
+                    == gold_triple.relation().map(universal_relation)
+                {
+                    self.universal_labeled_correct += 1;
+                    if !is_punct {
+                        self.nopunct_universal_labeled_correct += 1;
+                    }
+                }
+
+                if !is_punct {
+                    self.nopunct_unlabeled_correct += 1;
+                }
+            } else {
+                unlabeled_sentence_correct = false;
+            }
+
+            self.ud_metric_counts.add_token(sent1, sent2, idx);
+        }
+
+        // Unaligned words are only counted as gold or predicted relations.
+        for idx in pair.unaligned_indices() {
+            let gold_triple = sent1.dep_graph().head(idx);
+            let gold_relation = gold_triple
+                .as_ref()
+                .map(|triple| triple.relation().unwrap_or("_"));
+            if let Some(relation) = gold_relation {
+                self.relation_counts
+                    .entry(relation.to_owned())
+                    .or_default()
+                    .gold += 1;
+            }
+            self.ud_metric_counts.add_gold(gold_relation);
+        }
+
+        for word in pair.unaligned_predicted() {
+            if let Some(relation) = &word.relation {
+                self.relation_counts
+                    .entry(relation.clone())
+                    .or_default()
+                    .predicted += 1;
+            }
+            self.ud_metric_counts
+                .add_predicted(word.relation.as_deref());
+        }
+
+        self.sentences += 1;
+        if labeled_sentence_correct {
+            self.labeled_correct_sentences += 1;
+        }
+        if unlabeled_sentence_correct {
+            self.unlabeled_correct_sentences += 1;
+        }
+
+        Ok(())
+    }
+
+    fn scores(&self) -> Vec<Record> {
+        let mut records = vec![
+            Record::setting("punctuation", &self.punctuation),
+            Record::accuracy("LAS", self.labeled_correct, self.total),
+        ];
+        if self.universal_relations {
+            records.push(Record::accuracy(
+                "LAS:universal",
+                self.universal_labeled_correct,
+                self.total,
+            ));
+        }
+
+        records.push(Record::accuracy(
+            "LASnp",
+            self.nopunct_labeled_correct,
+            self.nopunct_total,
+        ));
+        if self.universal_relations {
+            records.push(Record::accuracy(
+                "LASnp:universal",
+                self.nopunct_universal_labeled_correct,
+                self.nopunct_total,
+            ));
+        }
+
+        records.push(Record::accuracy("UAS", self.unlabeled_correct, self.total));
+        records.push(Record::accuracy(
+            "UASnp",
+            self.nopunct_unlabeled_correct,
+            self.nopunct_total,
+        ));
+
+        if self.exact_match {
+            records.push(Record::accuracy(
+                "LAS:exact",
+                self.labeled_correct_sentences,
+                self.sentences,
+            ));
+            records.push(Record::accuracy(
+                "UAS:exact",
+                self.unlabeled_correct_sentences,
+                self.sentences,
+            ));
+        }
+
+        if self.ud_metrics {
+            records.push(self.ud_metric_counts.clas.record("CLAS", None));
+            records.push(self.ud_metric_counts.mlas.record("MLAS", None));
+            records.push(self.ud_metric_counts.blex.record("BLEX", None));
+        }
+
+        records
+    }
+
+    fn write_details(&self, writer: &mut dyn Write, output_format: OutputFormat) -> Result<()> {
+        if !self.per_relation {
+            return Ok(());
+        }
+
+        if output_format == OutputFormat::Text {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "Relation\tGold\tPredicted\tCorrect\tPrecision\tRecall\tF1"
+            )?;
+        }
+
+        let records = self
+            .relation_counts
+            .iter()
+            .map(|(relation, counts)| counts.record("Relation", Some(relation)))
+            .collect::<Vec<_>>();
+        write_records(writer, output_format, &records)?;
+
+        Ok(())
+    }
+}
+
+/// Counts for the CoNLL 2018 shared task metrics.
+#[derive(Default)]
+struct UdMetricCounts {
+    clas: F1Counts,
+    mlas: F1Counts,
+    blex: F1Counts,
+}
+
+impl UdMetricCounts {
+    /// Add a gold word that is not aligned to a predicted word.
+    fn add_gold(&mut self, relation: Option<&str>) {
+        if relation
+            .map(universal_relation)
+            .map(is_content_relation)
+            .unwrap_or(false)
+        {
+            self.clas.gold += 1;
+            self.mlas.gold += 1;
+            self.blex.gold += 1;
+        }
+    }
+
+    /// Add a predicted word that is not aligned to a gold word.
+    fn add_predicted(&mut self, relation: Option<&str>) {
+        if relation
+            .map(universal_relation)
+            .map(is_content_relation)
+            .unwrap_or(false)
+        {
+            self.clas.predicted += 1;
+            self.mlas.predicted += 1;
+            self.blex.predicted += 1;
+        }
+    }
+
+    /// Add the counts for the token at `idx`.
+    ///
+    /// Following the shared task evaluation script, relations are
+    /// compared without their subtypes and only universal features
+    /// are compared.
+    fn add_token(&mut self, gold: &Sentence, predicted: &Sentence, idx: usize) {
+        let gold_triple = gold.dep_graph().head(idx);
+        let predicted_triple = predicted.dep_graph().head(idx);
+        let gold_relation = gold_triple
+            .as_ref()
+            .and_then(|t| t.relation())
+            .map(universal_relation);
+        let predicted_relation = predicted_triple
+            .as_ref()
+            .and_then(|t| t.relation())
+            .map(universal_relation);
+
+        if predicted_relation.map(is_content_relation).unwrap_or(false) {
+            self.clas.predicted += 1;
+            self.mlas.predicted += 1;
+            self.blex.predicted += 1;
+        }
+
+        if !gold_relation.map(is_content_relation).unwrap_or(false) {
+            return;
+        }
+
+        self.clas.gold += 1;
+        self.mlas.gold += 1;
+        self.blex.gold += 1;
+
+        if gold_triple.as_ref().map(|t| t.head()) != predicted_triple.as_ref().map(|t| t.head())
+            || gold_relation != predicted_relation
+        {
+            return;
+        }
+
+        self.clas.correct += 1;
+
+        let gold_token = gold[idx].token().unwrap();
+        let predicted_token = predicted[idx].token().unwrap();
+
+        if gold_token.upos() == predicted_token.upos()
+            && universal_features(gold_token) == universal_features(predicted_token)
+            && function_children(gold, idx) == function_children(predicted, idx)
+        {
+            self.mlas.correct += 1;
+        }
+
+        if gold_token.lemma().is_none() || gold_token.lemma() == predicted_token.lemma() {
+            self.blex.correct += 1;
+        }
+    }
+}
+
+/// Relation, upos and universal features of a function word.
+type FunctionChild<'a> = (String, Option<&'a str>, Vec<(&'a str, &'a str)>);
+
+/// Get the function word dependents of the token at `idx`.
+fn function_children(sentence: &Sentence, idx: usize) -> Vec<FunctionChild<'_>> {
+    sentence
+        .dep_graph()
+        .dependents(idx)
+        .filter_map(|triple| {
+            let relation = universal_relation(triple.relation()?);
+            if !FUNCTION_RELATIONS.contains(&relation) {
+                return None;
+            }
+
+            let token = sentence[triple.dependent()].token()?;
+            Some((
+                triple.dependent(),
+                (relation.to_owned(), token.upos(), universal_features(token)),
+            ))
+        })
+        .sorted_by_key(|(dependent, _)| *dependent)
+        .map(|(_, child)| child)
+        .collect()
+}
+
+fn is_content_relation(relation: &str) -> bool {
+    CONTENT_RELATIONS.contains(&relation)
+}
@@ -0,0 +1,268 @@
+use std::io::Write;
+
+use anyhow::{ensure, Context, Result};
+use udgraph::graph::Sentence;
+
+use super::Evaluator;
+use crate::align::SentencePair;
+use crate::report::{write_records, OutputFormat, Record};
+
+/// Properties by which attachment scores can be broken down.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Breakdown {
+    /// Distance between a token and its head, bucketed by the given edges.
+    ArcLength(Vec<usize>),
+
+    /// Position of the head relative to the token.
+    Direction,
+
+    /// Number of arcs between a token and the root, bucketed by the
+    /// given edges.
+    RootDistance(Vec<usize>),
+
+    /// Number of tokens in the sentence, bucketed by the given edges.
+    SentenceLength(Vec<usize>),
+}
+
+impl Breakdown {
+    /// Get the name of the breakdown.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Breakdown::ArcLength(_) => "arc-length",
+            Breakdown::Direction => "direction",
+            Breakdown::RootDistance(_) => "root-distance",
+            Breakdown::SentenceLength(_) => "sentence-length",
+        }
+    }
+
+    /// Get the labels of the buckets.
+    fn labels(&self) -> Vec<String> {
+        match self {
+            Breakdown::ArcLength(edges) => {
+                let mut labels = vec!["root".to_owned()];
+                labels.extend(edge_labels(edges));
+                labels
+            }
+            Breakdown::Direction => vec!["left".to_owned(), "right".to_owned(), "root".to_owned()],
+            Breakdown::RootDistance(edges) | Breakdown::SentenceLength(edges) => edge_labels(edges),
+        }
+    }
+
+    /// Get the bucket of the token at `idx`.
+    ///
+    /// Returns `None` when the token does not have a head or is attached
+    /// to itself, which is the case for aligned tokens of which the head
+    /// is not aligned.
+    fn bucket(&self, sentence: &Sentence, idx: usize) -> Option<usize> {
+        let head = sentence.dep_graph().head(idx)?.head();
+        if head == idx {
+            return None;
+        }
+
+        match self {
+            Breakdown::ArcLength(edges) => {
+                if head == 0 {
+                    Some(0)
+                } else {
+                    let length = idx.abs_diff(head);
+                    Some(edge_bucket(edges, length) + 1)
+                }
+            }
+            Breakdown::Direction => Some(if head == 0 {
+                2
+            } else if head < idx {
+                0
+            } else {
+                1
+            }),
+            Breakdown::RootDistance(edges) => {
+                root_distance(sentence, idx).map(|distance| edge_bucket(edges, distance))
+            }
+            Breakdown::SentenceLength(edges) => Some(edge_bucket(edges, sentence.len() - 1)),
+        }
+    }
+}
+
+/// Parse comma-separated bucket edges.
+///
+/// Each edge is the lower bound of a bucket. An edge of 1 is added when
+/// absent, since all bucketed values are at least 1.
+pub fn parse_edges(edges: &str) -> Result<Vec<usize>> {
+    let mut edges = edges
+        .split(',')
+        .map(|edge| {
+            edge.trim()
+                .parse()
+                .context(format!("Cannot parse bucket edge: {}", edge))
+        })
+        .collect::<Result<Vec<usize>>>()?;
+
+    ensure!(
+        edges.iter().all(|&edge| edge > 0),
+        "Bucket edges should be positive"
+    );
+
+    edges.push(1);
+    edges.sort_unstable();
+    edges.dedup();
+
+    Ok(edges)
+}
+
+/// Get the index of the bucket that contains `value`.
+fn edge_bucket(edges: &[usize], value: usize) -> usize {
+    edges.iter().rposition(|&edge| edge <= value).unwrap_or(0)
+}
+
+/// Get bucket labels such as `3-4` or `10+`.
+fn edge_labels(edges: &[usize]) -> Vec<String> {
+    edges
+        .iter()
+        .enumerate()
+        .map(|(idx, &edge)| match edges.get(idx + 1) {
+            Some(&next) if next == edge + 1 => edge.to_string(),
+            Some(&next) => format!("{}-{}", edge, next - 1),
+            None => format!("{}+", edge),
+        })
+        .collect()
+}
+
+/// Get the number of arcs between the token at `idx` and the root.
+///
+/// Returns `None` when the path is broken or contains a cycle.
+fn root_distance(sentence: &Sentence, mut idx: usize) -> Option<usize> {
+    let dep_graph = sentence.dep_graph();
+
+    let mut distance = 0;
+    while idx != 0 {
+        if distance == sentence.len() {
+            return None;
+        }
+
+        idx = dep_graph.head(idx)?.head();
+        distance += 1;
+    }
+
+    Some(distance)
+}
+
+/// Counts of the tokens in a bucket.
+#[derive(Clone, Copy, Debug, Default)]
+struct BucketCounts {
+    gold: usize,
+    gold_correct: usize,
+    predicted: usize,
+    predicted_correct: usize,
+}
+
+/// Attachment scores broken down by a property of tokens.
+///
+/// Gold tokens are bucketed using the gold tree and predicted tokens
+/// using the predicted tree. Recall is the attachment score of the gold
+/// tokens in a bucket, precision that of the predicted tokens in a
+/// bucket. A correct attachment can be in different gold and predicted
+/// buckets, e.g. for root distance when an ancestor is attached
+/// incorrectly.
+pub struct BreakdownEvaluator {
+    breakdown: Breakdown,
+    labeled: Vec<BucketCounts>,
+    unlabeled: Vec<BucketCounts>,
+}
+
+impl BreakdownEvaluator {
+    pub fn new(breakdown: Breakdown) -> Self {
+        let n_buckets = breakdown.labels().len();
+
+        BreakdownEvaluator {
+            breakdown,
+            labeled: vec![BucketCounts::default(); n_buckets],
+            unlabeled: vec![BucketCounts::default(); n_buckets],
+        }
+    }
+
+    fn records(&self, metric: &str, counts: &[BucketCounts]) -> Vec<Record> {
+        let metric = format!("{}:{}", metric, self.breakdown.name());
+        self.breakdown
+            .labels()
+            .into_iter()
+            .zip(counts)
+            .map(|(label, counts)| {
+                Record::bucket(
+                    metric.as_str(),
+                    label,
+                    counts.gold,
+                    counts.gold_correct,
+                    counts.predicted,
+                    counts.predicted_correct,
+                )
+            })
+            .collect()
+    }
+}
+
+impl Evaluator for BreakdownEvaluator {
+    fn process(&mut self, pair: &SentencePair) -> Result<()> {
+        let (gold, predicted) = (&pair.gold, &pair.predicted);
+
+        for idx in pair.aligned_indices() {
+            let gold_bucket = self.breakdown.bucket(gold, idx);
+            let predicted_bucket = self.breakdown.bucket(predicted, idx);
+
+            if let Some(bucket) = gold_bucket {
+                self.labeled[bucket].gold += 1;
+                self.unlabeled[bucket].gold += 1;
+            }
+
+            if let Some(bucket) = predicted_bucket {
+                self.labeled[bucket].predicted += 1;
+                self.unlabeled[bucket].predicted += 1;
+            }
+
+            let gold_triple = gold.dep_graph().head(idx);
+            let predicted_triple = predicted.dep_graph().head(idx);
+
+            let unlabeled_correct = predicted_triple.is_some()
+                && gold_triple.as_ref().map(|t| t.head())
+                    == predicted_triple.as_ref().map(|t| t.head());
+            let labeled_correct = unlabeled_correct && gold_triple == predicted_triple;
+
+            for (counts, correct) in &mut [
+                (&mut self.labeled, labeled_correct),
+                (&mut self.unlabeled, unlabeled_correct),
+            ] {
+                if !*correct {
+                    continue;
+                }
+
+                if let Some(bucket) = gold_bucket {
+                    counts[bucket].gold_correct += 1;
+                }
+                if let Some(bucket) = predicted_bucket {
+                    counts[bucket].predicted_correct += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scores(&self) -> Vec<Record> {
+        Vec::new()
+    }
+
+    fn write_details(&self, writer: &mut dyn Write, output_format: OutputFormat) -> Result<()> {
+        for (metric, counts) in &[("LAS", &self.labeled), ("UAS", &self.unlabeled)] {
+            if output_format == OutputFormat::Text {
+                writeln!(writer, "\n{} by {}", metric, self.breakdown.name())?;
+                writeln!(
+                    writer,
+                    "Bucket\tGold\tPredicted\tGold correct\tPredicted correct\tPrecision\tRecall\tF1"
+                )?;
+            }
+
+            write_records(&mut *writer, output_format, &self.records(metric, counts))?;
+        }
+
+        Ok(())
+    }
+}
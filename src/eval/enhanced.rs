@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use super::{universal_relation, Evaluator, F1Counts};
+use crate::align::SentencePair;
+use crate::report::{write_records, OutputFormat, Record};
+
+/// Enhanced dependency scores.
+///
+/// Enhanced dependencies are read from the DEPS column. Following the
+/// IWPT 2020 shared task, each token's set of (head, relation) pairs is
+/// compared. ELAS is the F1 score of the enhanced dependencies, EULAS
+/// the F1 score when only the universal part of relations is compared.
+///
+/// The enhanced dependencies of empty nodes (such as `5.1`) are also
+/// evaluated. Empty nodes are matched by their identifiers, as are heads
+/// that are empty nodes.
+#[derive(Default)]
+pub struct EnhancedEvaluator {
+    per_relation: bool,
+
+    elas: F1Counts,
+    eulas: F1Counts,
+    relation_counts: BTreeMap<String, F1Counts>,
+}
+
+impl EnhancedEvaluator {
+    /// Construct an enhanced dependency evaluator.
+    ///
+    /// If `per_relation` is true, precision, recall and F1 are reported
+    /// per enhanced dependency relation.
+    pub fn new(per_relation: bool) -> Self {
+        EnhancedEvaluator {
+            per_relation,
+            ..Default::default()
+        }
+    }
+}
+
+impl EnhancedEvaluator {
+    /// Add the enhanced dependencies of a gold and a predicted token.
+    fn add_deps(&mut self, gold: &Deps, predicted: &Deps) {
+        add_counts(&mut self.elas, gold, predicted);
+        add_counts(
+            &mut self.eulas,
+            &universal_deps(gold),
+            &universal_deps(predicted),
+        );
+
+        for (_, relation) in gold {
+            self.relation_counts
+                .entry(relation.to_string())
+                .or_default()
+                .gold += 1;
+        }
+
+        for dep @ (_, relation) in predicted {
+            let counts = self
+                .relation_counts
+                .entry(relation.to_string())
+                .or_default();
+            counts.predicted += 1;
+            if gold.contains(dep) {
+                counts.correct += 1;
+            }
+        }
+    }
+}
+
+impl Evaluator for EnhancedEvaluator {
+    fn process(&mut self, pair: &SentencePair) -> Result<()> {
+        for idx in pair.aligned_indices() {
+            let gold = parse_deps(pair.gold[idx].token().unwrap().deps())?;
+            let predicted = parse_deps(pair.predicted[idx].token().unwrap().deps())?;
+            self.add_deps(&gold, &predicted);
+        }
+
+        // Unaligned words are only counted as gold or predicted dependencies.
+        for idx in pair.unaligned_indices() {
+            let gold = parse_deps(pair.gold[idx].token().unwrap().deps())?;
+            self.add_deps(&gold, &Deps::new());
+        }
+
+        for word in pair.unaligned_predicted() {
+            let predicted = parse_deps(word.token.deps())?;
+            self.add_deps(&Deps::new(), &predicted);
+        }
+
+        let empty_nodes = match pair.empty_nodes() {
+            Some(empty_nodes) => empty_nodes,
+            None => return Ok(()),
+        };
+
+        // Empty nodes are matched by their identifiers.
+        let mut predicted_nodes = empty_nodes
+            .predicted
+            .iter()
+            .map(|node| ((node.word, node.position), node))
+            .collect::<HashMap<_, _>>();
+        for node in &empty_nodes.gold {
+            let gold = parse_deps(node.deps.as_deref())?;
+            let predicted = match predicted_nodes.remove(&(node.word, node.position)) {
+                Some(predicted_node) => parse_deps(predicted_node.deps.as_deref())?,
+                None => Deps::new(),
+            };
+            self.add_deps(&gold, &predicted);
+        }
+
+        for node in predicted_nodes
+            .values()
+            .copied()
+            .chain(&empty_nodes.unaligned)
+        {
+            let predicted = parse_deps(node.deps.as_deref())?;
+            self.add_deps(&Deps::new(), &predicted);
+        }
+
+        Ok(())
+    }
+
+    fn scores(&self) -> Vec<Record> {
+        vec![
+            self.elas.record("ELAS", None),
+            self.eulas.record("EULAS", None),
+        ]
+    }
+
+    fn write_details(&self, writer: &mut dyn Write, output_format: OutputFormat) -> Result<()> {
+        if !self.per_relation {
+            return Ok(());
+        }
+
+        if output_format == OutputFormat::Text {
+            writeln!(writer)?;
+            writeln!(
+                writer,
+                "Enhanced relation\tGold\tPredicted\tCorrect\tPrecision\tRecall\tF1"
+            )?;
+        }
+
+        let records = self
+            .relation_counts
+            .iter()
+            .map(|(relation, counts)| counts.record("Enhanced relation", Some(relation)))
+            .collect::<Vec<_>>();
+        write_records(writer, output_format, &records)?;
+
+        Ok(())
+    }
+}
+
+/// Enhanced dependencies of a token as (head, relation) pairs.
+type Deps<'a> = BTreeSet<(&'a str, &'a str)>;
+
+fn add_counts(counts: &mut F1Counts, gold: &Deps, predicted: &Deps) {
+    counts.gold += gold.len();
+    counts.predicted += predicted.len();
+    counts.correct += gold.intersection(predicted).count();
+}
+
+/// Parse the DEPS column, e.g. `2:nsubj|4:nsubj:xsubj`.
+fn parse_deps(deps: Option<&str>) -> Result<Deps<'_>> {
+    let deps = match deps {
+        Some(deps) => deps,
+        None => return Ok(Deps::new()),
+    };
+
+    deps.split('|')
+        .map(|dep| match dep.split_once(':') {
+            Some((head, relation)) if !head.is_empty() && !relation.is_empty() => {
+                Ok((head, relation))
+            }
+            _ => bail!("Cannot parse enhanced dependency: {}", dep),
+        })
+        .collect()
+}
+
+fn universal_deps<'a>(deps: &Deps<'a>) -> Deps<'a> {
+    deps.iter()
+        .map(|&(head, relation)| (head, universal_relation(relation)))
+        .collect()
+}
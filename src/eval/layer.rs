@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use super::Evaluator;
+use crate::align::SentencePair;
+use crate::layer::LayerCallback;
+use crate::report::{write_records, OutputFormat, Record};
+
+/// Accuracy of a single layer.
+pub struct LayerEvaluator<'a> {
+    name: String,
+    callback: &'a LayerCallback,
+    default: Option<&'a str>,
+    confusion_matrix: bool,
+    exact_match: bool,
+    top_confusions: Option<usize>,
+
+    confusions: Confusions,
+    correct: usize,
+    total: usize,
+
+    correct_sentences: usize,
+    sentences: usize,
+}
+
+impl<'a> LayerEvaluator<'a> {
+    /// Construct an evaluator for the layer extracted by `callback`.
+    ///
+    /// When `default` is set, it is used in place of absent values.
+    pub fn new(
+        name: impl Into<String>,
+        callback: &'a LayerCallback,
+        default: Option<&'a str>,
+    ) -> Self {
+        LayerEvaluator {
+            name: name.into(),
+            callback,
+            default,
+            confusion_matrix: false,
+            exact_match: false,
+            top_confusions: None,
+
+            confusions: Confusions::default(),
+            correct: 0,
+            total: 0,
+
+            correct_sentences: 0,
+            sentences: 0,
+        }
+    }
+
+    /// Report the confusion matrix (gold x predicted).
+    pub fn confusion_matrix(mut self, confusion_matrix: bool) -> Self {
+        self.confusion_matrix = confusion_matrix;
+        self
+    }
+
+    /// Report the percentage of sentences in which all tokens are correct.
+    pub fn exact_match(mut self, exact_match: bool) -> Self {
+        self.exact_match = exact_match;
+        self
+    }
+
+    /// Report the `n` most frequent confusions.
+    pub fn top_confusions(mut self, n: Option<usize>) -> Self {
+        self.top_confusions = n;
+        self
+    }
+}
+
+impl<'a> Evaluator for LayerEvaluator<'a> {
+    fn process(&mut self, pair: &SentencePair) -> Result<()> {
+        let mut sentence_correct = pair.is_fully_aligned();
+
+        for idx in pair.aligned_indices() {
+            self.total += 1;
+
+            let gold = (self.callback)(&pair.gold, idx).or_else(|| self.default.map(Cow::Borrowed));
+            let predicted =
+                (self.callback)(&pair.predicted, idx).or_else(|| self.default.map(Cow::Borrowed));
+
+            if gold == predicted {
+                self.correct += 1
+            } else {
+                sentence_correct = false;
+            }
+
+            self.confusions.add(
+                gold.as_deref().unwrap_or("_"),
+                predicted.as_deref().unwrap_or("_"),
+            );
+        }
+
+        self.sentences += 1;
+        if sentence_correct {
+            self.correct_sentences += 1;
+        }
+
+        Ok(())
+    }
+
+    fn scores(&self) -> Vec<Record> {
+        let mut records = vec![Record::accuracy(&self.name, self.correct, self.total)];
+
+        if self.exact_match {
+            records.push(Record::accuracy(
+                format!("{}:exact", self.name),
+                self.correct_sentences,
+                self.sentences,
+            ));
+        }
+
+        records
+    }
+
+    fn write_details(&self, writer: &mut dyn Write, output_format: OutputFormat) -> Result<()> {
+        if output_format != OutputFormat::Text {
+            if self.confusion_matrix {
+                write_records(
+                    &mut *writer,
+                    output_format,
+                    &self.confusions.records(&self.name),
+                )?;
+            }
+
+            if let Some(n) = self.top_confusions {
+                write_records(
+                    writer,
+                    output_format,
+                    &self.confusions.top_records(&self.name, n),
+                )?;
+            }
+
+            return Ok(());
+        }
+
+        if self.confusion_matrix {
+            writeln!(writer, "\nConfusion matrix: {}", self.name)?;
+            self.confusions.write_matrix(writer)?;
+        }
+
+        if let Some(n) = self.top_confusions {
+            writeln!(writer, "\nConfusions: {}", self.name)?;
+            writeln!(writer, "Gold\tPredicted\tCount")?;
+            write_records(
+                writer,
+                output_format,
+                &self.confusions.top_records(&self.name, n),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts of (gold, predicted) value pairs.
+#[derive(Default)]
+struct Confusions {
+    counts: BTreeMap<(String, String), usize>,
+}
+
+impl Confusions {
+    fn add(&mut self, gold: &str, predicted: &str) {
+        *self
+            .counts
+            .entry((gold.to_owned(), predicted.to_owned()))
+            .or_default() += 1;
+    }
+
+    /// Write the confusion matrix. Rows are gold values, columns are
+    /// predicted values.
+    fn write_matrix(&self, writer: &mut dyn Write) -> Result<()> {
+        let gold_labels: BTreeSet<&str> = self.counts.keys().map(|(g, _)| g.as_str()).collect();
+        let predicted_labels: BTreeSet<&str> =
+            self.counts.keys().map(|(_, p)| p.as_str()).collect();
+
+        writeln!(
+            writer,
+            "Gold\\Predicted\t{}",
+            predicted_labels.iter().join("\t")
+        )?;
+
+        for gold in gold_labels {
+            let mut row = predicted_labels.iter().map(|predicted| {
+                self.counts
+                    .get(&(gold.to_owned(), (*predicted).to_owned()))
+                    .copied()
+                    .unwrap_or(0)
+            });
+            writeln!(writer, "{}\t{}", gold, row.join("\t"))?;
+        }
+
+        Ok(())
+    }
+
+    fn records(&self, metric: &str) -> Vec<Record> {
+        self.counts
+            .iter()
+            .map(|((gold, predicted), &count)| Record::confusion(metric, gold, predicted, count))
+            .collect()
+    }
+
+    /// Get the `n` most frequent confusions, excluding correct
+    /// predictions.
+    fn top_records(&self, metric: &str, n: usize) -> Vec<Record> {
+        self.counts
+            .iter()
+            .filter(|((gold, predicted), _)| gold != predicted)
+            .sorted_by_key(|(_, &count)| Reverse(count))
+            .take(n)
+            .map(|((gold, predicted), &count)| Record::confusion(metric, gold, predicted, count))
+            .collect()
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::Write;
+use std::ops::AddAssign;
+
+use anyhow::Result;
+
+use crate::align::SentencePair;
+use crate::report::{f1_score, percentage, OutputFormat, Record};
+
+mod attachment;
+pub use attachment::AttachmentEvaluator;
+
+mod breakdown;
+pub use breakdown::{parse_edges, Breakdown, BreakdownEvaluator};
+
+mod enhanced;
+pub use enhanced::EnhancedEvaluator;
+
+mod layer;
+pub use layer::LayerEvaluator;
+
+mod punctuation;
+pub use punctuation::PunctuationPolicy;
+
+mod vocabulary;
+pub use vocabulary::{Vocabulary, VocabularyEvaluator, VocabularyLayer};
+
+/// Evaluation of predicted sentences against gold standard sentences.
+///
+/// An evaluator accumulates counts over sentence pairs, so that several
+/// evaluators can be run in a single pass over two treebanks.
+pub trait Evaluator {
+    /// Add the aligned tokens of a sentence pair to the evaluation.
+    fn process(&mut self, pair: &SentencePair) -> Result<()>;
+
+    /// Get the scores of the evaluation.
+    fn scores(&self) -> Vec<Record>;
+
+    /// Write detailed results, such as per-label scores.
+    fn write_details(&self, writer: &mut dyn Write, output_format: OutputFormat) -> Result<()>;
+}
+
+/// Counts for computing precision, recall and F1.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct F1Counts {
+    pub gold: usize,
+    pub predicted: usize,
+    pub correct: usize,
+}
+
+impl F1Counts {
+    /// Get the counts of an accuracy or F1 record.
+    ///
+    /// The gold and predicted counts of an accuracy record are both
+    /// equal to its total, so that the F1 score equals the accuracy.
+    pub fn from_record(record: &Record) -> Option<Self> {
+        match *record {
+            Record::Accuracy { correct, total, .. } => Some(F1Counts {
+                gold: total,
+                predicted: total,
+                correct,
+            }),
+            Record::F1 {
+                correct,
+                gold,
+                predicted,
+                ..
+            } => Some(F1Counts {
+                gold,
+                predicted,
+                correct,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn f1(&self) -> f64 {
+        f1_score(
+            percentage(self.correct, self.predicted),
+            percentage(self.correct, self.gold),
+        )
+    }
+
+    pub fn record(&self, metric: &str, label: Option<&str>) -> Record {
+        Record::f1(
+            metric,
+            label.map(ToOwned::to_owned),
+            self.correct,
+            self.gold,
+            self.predicted,
+        )
+    }
+}
+
+impl AddAssign for F1Counts {
+    fn add_assign(&mut self, other: Self) {
+        self.gold += other.gold;
+        self.predicted += other.predicted;
+        self.correct += other.correct;
+    }
+}
+
+/// Aggregate the scores of folds.
+///
+/// Only accuracy and F1 records are aggregated. Metrics are reported in
+/// the order in which they first occur.
+pub fn aggregate_records(folds: &[Vec<Record>]) -> Vec<Record> {
+    let mut metrics: Vec<(&str, Vec<F1Counts>)> = Vec::new();
+    for fold in folds {
+        for record in fold {
+            let (metric, counts) = match (record, F1Counts::from_record(record)) {
+                (Record::Accuracy { metric, .. }, Some(counts))
+                | (Record::F1 { metric, .. }, Some(counts)) => (metric.as_str(), counts),
+                _ => continue,
+            };
+
+            match metrics.iter_mut().find(|(m, _)| *m == metric) {
+                Some((_, fold_counts)) => fold_counts.push(counts),
+                None => metrics.push((metric, vec![counts])),
+            }
+        }
+    }
+
+    metrics
+        .into_iter()
+        .map(|(metric, fold_counts)| {
+            let mut total = F1Counts::default();
+            for &counts in &fold_counts {
+                total += counts;
+            }
+
+            let scores = fold_counts.iter().map(F1Counts::f1).collect::<Vec<_>>();
+            let n = scores.len() as f64;
+            let mean = scores.iter().sum::<f64>() / n;
+            let std_dev = if scores.len() > 1 {
+                (scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.)).sqrt()
+            } else {
+                0.
+            };
+
+            Record::Aggregate {
+                metric: metric.to_owned(),
+                micro_average: total.f1(),
+                macro_average: mean,
+                std_dev,
+                folds: scores.len(),
+            }
+        })
+        .collect()
+}
+
+/// Strip the subtype of a relation.
+pub fn universal_relation(relation: &str) -> &str {
+    relation.split(':').next().unwrap_or(relation)
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use udgraph::graph::Sentence;
+use unicode_categories::UnicodeCategories;
+
+/// Policies for excluding punctuation from attachment scores.
+///
+/// Punctuation is always determined using the gold standard sentence.
+#[derive(Clone, Debug, Default)]
+pub enum PunctuationPolicy {
+    /// Tokens with the `punct` relation or one of its subtypes.
+    Deprel,
+
+    /// Tokens of which all characters are Unicode punctuation.
+    #[default]
+    Form,
+
+    /// Tokens of which the form matches a regular expression.
+    Regex(Regex),
+
+    /// Tokens with the `PUNCT` universal part-of-speech tag. If `symbols`
+    /// is true, tokens with the `SYM` tag are also excluded.
+    Upos { symbols: bool },
+}
+
+impl PunctuationPolicy {
+    /// Construct a policy from its name.
+    ///
+    /// A regular expression must be provided for the `regex` policy. The
+    /// expression must match the complete form.
+    pub fn from_name(name: &str, regex: Option<&str>) -> Result<Self> {
+        Ok(match name {
+            "deprel" => PunctuationPolicy::Deprel,
+            "form" => PunctuationPolicy::Form,
+            "regex" => {
+                let regex = match regex {
+                    Some(regex) => regex,
+                    None => bail!("The regex punctuation policy requires a regular expression"),
+                };
+                PunctuationPolicy::Regex(
+                    Regex::new(&format!("^(?:{})$", regex))
+                        .context(format!("Cannot parse regular expression: {}", regex))?,
+                )
+            }
+            "upos" => PunctuationPolicy::Upos { symbols: false },
+            "upos-sym" => PunctuationPolicy::Upos { symbols: true },
+            _ => bail!("Unknown punctuation policy: {}", name),
+        })
+    }
+
+    /// Check whether the token at `idx` is punctuation.
+    pub fn is_punctuation(&self, sentence: &Sentence, idx: usize) -> bool {
+        let token = sentence[idx].token().unwrap();
+
+        match self {
+            PunctuationPolicy::Deprel => sentence
+                .dep_graph()
+                .head(idx)
+                .and_then(|triple| {
+                    triple
+                        .relation()
+                        .map(|r| r.split(':').next() == Some("punct"))
+                })
+                .unwrap_or(false),
+            PunctuationPolicy::Form => token.form().chars().all(|c| c.is_punctuation()),
+            PunctuationPolicy::Regex(regex) => regex.is_match(token.form()),
+            PunctuationPolicy::Upos { symbols } => match token.upos() {
+                Some("PUNCT") => true,
+                Some("SYM") => *symbols,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PunctuationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PunctuationPolicy::Deprel => write!(f, "deprel:punct"),
+            PunctuationPolicy::Form => write!(f, "form"),
+            PunctuationPolicy::Regex(regex) => write!(f, "regex:{}", regex),
+            PunctuationPolicy::Upos { symbols: false } => write!(f, "upos:PUNCT"),
+            PunctuationPolicy::Upos { symbols: true } => write!(f, "upos:PUNCT,SYM"),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use anyhow::{bail, Context, Result};
+use conllu::Error;
+use udgraph::graph::Sentence;
+use udgraph::token::Token;
+
+use super::Evaluator;
+use crate::align::SentencePair;
+use crate::report::{OutputFormat, Record};
+
+/// Layers from which a vocabulary can be built.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VocabularyLayer {
+    Form,
+    Lemma,
+}
+
+impl VocabularyLayer {
+    pub fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "form" => VocabularyLayer::Form,
+            "lemma" => VocabularyLayer::Lemma,
+            _ => bail!("Unknown vocabulary layer: {}", name),
+        })
+    }
+
+    fn value(self, token: &Token) -> Option<&str> {
+        match self {
+            VocabularyLayer::Form => Some(token.form()),
+            VocabularyLayer::Lemma => token.lemma(),
+        }
+    }
+}
+
+/// Vocabulary of a (training) treebank.
+pub struct Vocabulary {
+    layer: VocabularyLayer,
+    words: HashSet<String>,
+}
+
+impl Vocabulary {
+    /// Build the vocabulary of a treebank from the given layer.
+    pub fn from_treebank(
+        treebank: impl IntoIterator<Item = Result<Sentence, Error>>,
+        layer: VocabularyLayer,
+    ) -> Result<Self> {
+        let mut words = HashSet::new();
+
+        for sentence in treebank {
+            let sentence = sentence.context("Cannot read sentence from training treebank")?;
+            for token in sentence.iter().filter_map(|node| node.token()) {
+                if let Some(word) = layer.value(token) {
+                    words.insert(word.to_owned());
+                }
+            }
+        }
+
+        Ok(Vocabulary { layer, words })
+    }
+
+    /// Check whether a token is in the vocabulary.
+    pub fn contains(&self, token: &Token) -> bool {
+        self.layer
+            .value(token)
+            .map(|word| self.words.contains(word))
+            .unwrap_or(false)
+    }
+}
+
+/// Evaluation of in-vocabulary and out-of-vocabulary tokens.
+///
+/// Whether a token is in the vocabulary is determined using the gold
+/// standard token, or the predicted token for predicted words that are
+/// not aligned to a gold standard token. Empty nodes are not words, so
+/// they are not evaluated. The metrics of the wrapped evaluators are suffixed with
+/// `:iv` or `:oov`.
+pub struct VocabularyEvaluator<'a> {
+    in_vocabulary: Vec<Box<dyn Evaluator + 'a>>,
+    out_of_vocabulary: Vec<Box<dyn Evaluator + 'a>>,
+    vocabulary: &'a Vocabulary,
+}
+
+impl<'a> VocabularyEvaluator<'a> {
+    /// Wrap `in_vocabulary` to evaluate tokens that are in the vocabulary
+    /// and `out_of_vocabulary` to evaluate tokens that are not.
+    pub fn new(
+        vocabulary: &'a Vocabulary,
+        in_vocabulary: Vec<Box<dyn Evaluator + 'a>>,
+        out_of_vocabulary: Vec<Box<dyn Evaluator + 'a>>,
+    ) -> Self {
+        VocabularyEvaluator {
+            in_vocabulary,
+            out_of_vocabulary,
+            vocabulary,
+        }
+    }
+}
+
+impl<'a> Evaluator for VocabularyEvaluator<'a> {
+    fn process(&mut self, pair: &SentencePair) -> Result<()> {
+        let in_vocabulary = pair
+            .gold
+            .iter()
+            .map(|node| {
+                node.token()
+                    .map(|token| self.vocabulary.contains(token))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+        let unaligned_in_vocabulary = pair
+            .all_unaligned_predicted()
+            .iter()
+            .map(|word| self.vocabulary.contains(&word.token))
+            .collect::<Vec<_>>();
+
+        let in_vocabulary_pair =
+            pair.filter(|idx| in_vocabulary[idx], |idx| unaligned_in_vocabulary[idx]);
+        for evaluator in &mut self.in_vocabulary {
+            evaluator.process(&in_vocabulary_pair)?;
+        }
+
+        let out_of_vocabulary_pair = pair.filter(
+            |idx| !in_vocabulary[idx],
+            |idx| !unaligned_in_vocabulary[idx],
+        );
+        for evaluator in &mut self.out_of_vocabulary {
+            evaluator.process(&out_of_vocabulary_pair)?;
+        }
+
+        Ok(())
+    }
+
+    fn scores(&self) -> Vec<Record> {
+        let mut records = Vec::new();
+        for (evaluators, suffix) in &[
+            (&self.in_vocabulary, "iv"),
+            (&self.out_of_vocabulary, "oov"),
+        ] {
+            for evaluator in evaluators.iter() {
+                records.extend(evaluator.scores().into_iter().filter_map(|mut record| {
+                    let metric = record.metric_mut()?;
+                    *metric = format!("{}:{}", metric, suffix);
+                    Some(record)
+                }));
+            }
+        }
+
+        records
+    }
+
+    fn write_details(&self, writer: &mut dyn Write, output_format: OutputFormat) -> Result<()> {
+        for evaluator in self.in_vocabulary.iter().chain(&self.out_of_vocabulary) {
+            evaluator.write_details(writer, output_format)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// Style sheet of HTML reports.
+static STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+h2 { font-size: 1.1em; margin-top: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }
+th { background: #eee; }
+td.diff { background: #fcc; }
+.arcs { display: flex; gap: 2em; flex-wrap: wrap; }
+.arcs figcaption { font-size: 0.8em; color: #666; }
+svg text { font-size: 12px; }
+svg .arc { fill: none; stroke: #333; }
+svg .head { fill: #333; }
+svg .label { fill: #555; font-size: 10px; }
+svg .diff { fill: #c00; }
+svg .arc.diff { fill: none; stroke: #c00; }
+";
+
+/// Escape text for use in HTML.
+pub fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    Cow::Owned(escaped)
+}
+
+/// Write a self-contained HTML page.
+///
+/// `body` is written as-is and should already be escaped.
+pub fn write_page(writer: &mut dyn Write, title: &str, body: &str) -> io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html>")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>{}</title>", escape(title))?;
+    writeln!(writer, "<style>{}</style>", STYLE)?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<h1>{}</h1>", escape(title))?;
+    write!(writer, "{}", body)?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
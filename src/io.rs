@@ -1,46 +1,547 @@
+use std::borrow::Borrow;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Write};
 use std::path::Path;
 
-use conllu::io::{Reader, Writer};
-use flate2::read::GzDecoder;
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use conllu::display::ConlluSentence;
+use conllu::io::{ReadSentence, Reader, Writer};
+use conllu::{Error, ParseError};
+use flate2::read::MultiGzDecoder;
 use flate2::write::GzEncoder;
+use stdinout::{Input, Output};
+use udgraph::graph::Sentence;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
 
-pub fn create_writer<P>(filename: P, gzip: bool) -> io::Result<Writer<Box<dyn Write>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::create(filename)?;
-    let boxed_writer: Box<dyn Write> = if gzip {
-        Box::new(BufWriter::new(GzEncoder::new(file, Default::default())))
-    } else {
-        Box::new(BufWriter::new(file))
+/// Maximum length of the magic bytes of the supported compression formats.
+const MAX_MAGIC_LEN: usize = 6;
+
+/// Compression formats.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Bzip2,
+    Gzip,
+    None,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression format from the magic bytes at the start of
+    /// the data.
+    fn from_magic(magic: &[u8]) -> Self {
+        if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Infer the compression format from the extension of a path.
+    fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("bz2") => Compression::Bzip2,
+            Some("gz") => Compression::Gzip,
+            Some("xz") => Compression::Xz,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Get a compression format by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bzip2" => Some(Compression::Bzip2),
+            "gzip" => Some(Compression::Gzip),
+            "none" => Some(Compression::None),
+            "xz" => Some(Compression::Xz),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn default_level(self) -> u32 {
+        match self {
+            Compression::Bzip2 => 6,
+            Compression::Gzip => 6,
+            Compression::None => 0,
+            Compression::Xz => 6,
+            Compression::Zstd => 3,
+        }
+    }
+
+    fn level_range(self) -> (u32, u32) {
+        match self {
+            Compression::Bzip2 => (1, 9),
+            Compression::Gzip => (0, 9),
+            Compression::None => (0, 0),
+            Compression::Xz => (0, 9),
+            Compression::Zstd => (1, 22),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Compression::Bzip2 => "bzip2",
+            Compression::Gzip => "gzip",
+            Compression::None => "none",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Compression of output.
+///
+/// When no compression format is set, the format is inferred from the
+/// extension of the output file. Output to the standard output is not
+/// compressed unless a compression format is set.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OutputCompression {
+    compression: Option<Compression>,
+    level: Option<u32>,
+}
+
+impl OutputCompression {
+    /// Set the compression format.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Set the compression level.
+    ///
+    /// The default level of the compression format is used when no level
+    /// is set.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Wrap a writer in a compressor.
+    ///
+    /// `path` is the path of the output file, if any, and is used to infer
+    /// the compression format when it is not set.
+    pub fn compress<'a, W>(&self, write: W, path: Option<&Path>) -> io::Result<Box<dyn Write + 'a>>
+    where
+        W: 'a + Write,
+    {
+        let compression = match self.compression {
+            Some(compression) => compression,
+            None => path
+                .map(Compression::from_extension)
+                .unwrap_or(Compression::None),
+        };
+
+        // The level is ignored when the output is not compressed, e.g. when
+        // writing to the standard output without a compression format.
+        let level = match self.level {
+            Some(level) if compression != Compression::None => {
+                let (min, max) = compression.level_range();
+                if level < min || level > max {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Compression level {} is not in the range {}-{} of {}",
+                            level, min, max, compression
+                        ),
+                    ));
+                }
+                level
+            }
+            _ => compression.default_level(),
+        };
+
+        Ok(match compression {
+            Compression::Bzip2 => Box::new(BzEncoder::new(write, bzip2::Compression::new(level))),
+            Compression::Gzip => Box::new(GzEncoder::new(write, flate2::Compression::new(level))),
+            Compression::None => Box::new(write),
+            Compression::Xz => Box::new(XzEncoder::new(write, level)),
+            Compression::Zstd => Box::new(ZstdEncoder::new(write, level as i32)?.auto_finish()),
+        })
+    }
+}
+
+/// Open a file or standard output for writing.
+///
+/// The output is compressed using the given compression settings.
+pub fn output_write<'a>(
+    output: &'a Output,
+    compression: &OutputCompression,
+) -> io::Result<Box<dyn Write + 'a>> {
+    let path = match output {
+        Output::File(path) => Some(path.as_path()),
+        Output::Stdout(_) => None,
     };
 
-    Ok(Writer::new(boxed_writer))
+    compression.compress(output.write()?, path)
 }
 
+/// Open a treebank for reading.
+///
+/// The treebank is decompressed transparently when it is compressed.
 pub fn open_reader<P>(path: &P) -> io::Result<Reader<Box<dyn BufRead>>>
 where
     P: AsRef<Path>,
 {
     let f = File::open(path)?;
+    Ok(Reader::new(decompress(f)?))
+}
 
-    let boxed_reader: Box<dyn BufRead> = if path.as_ref().extension() == Some(OsStr::new("gz")) {
-        Box::new(BufReader::new(GzDecoder::new(f)))
-    } else {
-        Box::new(BufReader::new(f))
-    };
+/// Open a treebank for reading its words.
+///
+/// The treebank is decompressed transparently when it is compressed.
+pub fn open_word_reader<P>(path: &P) -> io::Result<WordReader<Box<dyn BufRead>>>
+where
+    P: AsRef<Path>,
+{
+    let f = File::open(path)?;
+    Ok(WordReader::new(decompress(f)?))
+}
 
-    Ok(Reader::new(boxed_reader))
+/// Open a treebank from a file or standard input for reading.
+///
+/// The treebank is decompressed transparently when it is compressed.
+pub fn input_reader(input: &Input) -> io::Result<Reader<Box<dyn BufRead + '_>>> {
+    Ok(Reader::new(input_buf_read(input)?))
 }
 
-pub fn open_writer<P>(path: &P) -> io::Result<Writer<Box<dyn Write>>>
+/// Open a file or standard input for reading.
+///
+/// The input is decompressed transparently when it is compressed.
+pub fn input_buf_read(input: &Input) -> io::Result<Box<dyn BufRead + '_>> {
+    decompress(input.buf_read()?)
+}
+
+/// Wrap a reader in a decompressor.
+///
+/// The compression format is detected using the magic bytes at the start
+/// of the data. Uncompressed data is read as-is.
+pub fn decompress<'a, R>(mut read: R) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: 'a + Read,
+{
+    // Read the magic bytes. A single read may return fewer bytes than
+    // requested, e.g. when reading from a pipe.
+    let mut magic = [0u8; MAX_MAGIC_LEN];
+    let mut magic_len = 0;
+    while magic_len < MAX_MAGIC_LEN {
+        match read.read(&mut magic[magic_len..]) {
+            Ok(0) => break,
+            Ok(n) => magic_len += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    let compression = Compression::from_magic(&magic[..magic_len]);
+    let read = Cursor::new(magic[..magic_len].to_vec()).chain(read);
+
+    Ok(match compression {
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(read))),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(read))),
+        Compression::None => Box::new(BufReader::new(read)),
+        Compression::Xz => Box::new(BufReader::new(XzDecoder::new_multi_decoder(read))),
+        Compression::Zstd => Box::new(BufReader::new(ZstdDecoder::new(read)?)),
+    })
+}
+
+/// Open a treebank for writing.
+///
+/// The treebank is compressed using the given compression settings.
+pub fn open_writer<P>(
+    path: &P,
+    compression: &OutputCompression,
+) -> io::Result<Writer<Box<dyn Write>>>
 where
     P: AsRef<Path>,
 {
-    let compress = path.as_ref().extension() == Some(OsStr::new("gz"));
-    create_writer(path, compress)
+    Ok(Writer::new(create_file(path, compression)?))
+}
+
+/// Open a treebank for writing sentences with their multi-word tokens and
+/// empty nodes.
+///
+/// The treebank is compressed using the given compression settings.
+pub fn open_word_writer<P>(
+    path: &P,
+    compression: &OutputCompression,
+) -> io::Result<WordWriter<Box<dyn Write>>>
+where
+    P: AsRef<Path>,
+{
+    Ok(WordWriter::new(create_file(path, compression)?))
+}
+
+fn create_file<P>(path: &P, compression: &OutputCompression) -> io::Result<Box<dyn Write>>
+where
+    P: AsRef<Path>,
+{
+    let file = File::create(path)?;
+    Ok(Box::new(BufWriter::new(
+        compression.compress(file, Some(path.as_ref()))?,
+    )))
+}
+
+/// A multi-word token, such as Spanish *del* for the words *de el*.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MultiwordToken {
+    /// Index of the first word of the token.
+    pub first: usize,
+
+    /// Index of the last word of the token.
+    pub last: usize,
+
+    pub form: String,
+
+    /// The range line of the token.
+    pub line: String,
+}
+
+/// An empty node, such as `5.1`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EmptyNode {
+    /// Index of the word after which the empty node is inserted.
+    pub word: usize,
+
+    /// Position of the empty node among the empty nodes after the word,
+    /// starting at 1.
+    pub position: usize,
+
+    pub form: String,
+
+    /// Enhanced dependencies of the empty node (the DEPS column).
+    pub deps: Option<String>,
+
+    /// The line of the empty node.
+    pub line: String,
+}
+
+impl EmptyNode {
+    /// Get the identifier of the empty node, such as `5.1`.
+    pub fn id(&self) -> String {
+        format!("{}.{}", self.word, self.position)
+    }
+}
+
+/// A sentence with its multi-word tokens and empty nodes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Words {
+    pub sentence: Sentence,
+    pub multiword_tokens: Vec<MultiwordToken>,
+    pub empty_nodes: Vec<EmptyNode>,
+}
+
+impl Borrow<Sentence> for Words {
+    fn borrow(&self) -> &Sentence {
+        &self.sentence
+    }
+}
+
+/// Reader for treebanks with multi-word tokens and empty nodes.
+///
+/// The CoNLL-U reader cannot parse the range lines of multi-word tokens
+/// and the lines of empty nodes (such as `5.1`). This reader removes these
+/// lines before parsing, so that sentences consist of their (syntactic)
+/// words. The multi-word tokens and empty nodes of a sentence can be read
+/// along with the sentence using `read_words`.
+pub struct WordReader<R> {
+    read: R,
+}
+
+impl<R: BufRead> WordReader<R> {
+    pub fn new(read: R) -> Self {
+        WordReader { read }
+    }
+
+    /// Read a sentence along with its multi-word tokens and empty nodes.
+    pub fn read_words(&mut self) -> Result<Option<Words>, Error> {
+        let mut lines = String::new();
+        let mut multiword_tokens = Vec::new();
+        let mut empty_nodes = Vec::new();
+        let mut has_words = false;
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.read.read_line(&mut line)? == 0 {
+                break;
+            }
+
+            // Like the CoNLL-U reader, skip blank lines before the first
+            // word of a sentence.
+            if line.trim().is_empty() {
+                if has_words {
+                    break;
+                }
+                continue;
+            }
+
+            if !line.starts_with('#') {
+                let mut fields = line.trim().split('\t');
+                let id = fields.next().unwrap_or_default();
+                if let Some((word, position)) = id.split_once('.') {
+                    let form = fields.next().ok_or(ParseError::MissingFormField)?;
+                    let deps = fields.nth(6).filter(|&deps| deps != "_");
+                    empty_nodes.push(EmptyNode {
+                        word: parse_word_id(word)?,
+                        position: parse_word_id(position)?,
+                        form: form.to_owned(),
+                        deps: deps.map(ToOwned::to_owned),
+                        line: line.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+                    });
+                    continue;
+                }
+
+                if let Some((first, last)) = id.split_once('-') {
+                    multiword_tokens.push(MultiwordToken {
+                        first: parse_word_id(first)?,
+                        last: parse_word_id(last)?,
+                        form: fields
+                            .next()
+                            .ok_or(ParseError::MissingFormField)?
+                            .to_owned(),
+                        line: line.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+                    });
+                    continue;
+                }
+
+                has_words = true;
+            }
+
+            lines.push_str(&line);
+        }
+
+        Ok(Reader::new(lines.as_bytes())
+            .read_sentence()?
+            .map(|sentence| Words {
+                sentence,
+                multiword_tokens,
+                empty_nodes,
+            }))
+    }
+
+    /// Get an iterator over the sentences with their multi-word tokens and
+    /// empty nodes.
+    pub fn words(mut self) -> impl Iterator<Item = Result<Words, Error>> {
+        std::iter::from_fn(move || self.read_words().transpose())
+    }
+}
+
+impl<R: BufRead> ReadSentence for WordReader<R> {
+    fn read_sentence(&mut self) -> Result<Option<Sentence>, Error> {
+        Ok(self.read_words()?.map(|words| words.sentence))
+    }
+}
+
+/// Writer for treebanks with multi-word tokens and empty nodes.
+///
+/// The range lines of multi-word tokens and the lines of empty nodes are
+/// written as they were read by `WordReader`.
+pub struct WordWriter<W> {
+    write: W,
+}
+
+impl<W: Write> WordWriter<W> {
+    pub fn new(write: W) -> Self {
+        WordWriter { write }
+    }
+
+    /// Write a sentence along with its multi-word tokens and empty nodes.
+    pub fn write_words(&mut self, words: &Words) -> Result<(), Error> {
+        let sentence = ConlluSentence::borrowed(&words.sentence).to_string();
+        let mut lines = sentence.lines();
+        for line in lines.by_ref().take(words.sentence.comments().len()) {
+            writeln!(self.write, "{}", line)?;
+        }
+
+        self.write_empty_nodes(words, 0)?;
+        for (idx, line) in (1..).zip(lines) {
+            for token in words
+                .multiword_tokens
+                .iter()
+                .filter(|token| token.first == idx)
+            {
+                writeln!(self.write, "{}", token.line)?;
+            }
+
+            writeln!(self.write, "{}", line)?;
+            self.write_empty_nodes(words, idx)?;
+        }
+
+        writeln!(self.write)?;
+
+        Ok(())
+    }
+
+    /// Write the empty nodes after the word at `idx`.
+    fn write_empty_nodes(&mut self, words: &Words, idx: usize) -> Result<(), Error> {
+        for node in words.empty_nodes.iter().filter(|node| node.word == idx) {
+            writeln!(self.write, "{}", node.line)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_word_id(id: &str) -> Result<usize, ParseError> {
+    id.parse().map_err(|_| ParseError::ParseIdentifierField {
+        value: id.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WordReader, WordWriter};
+
+    static DEL_PERRO: &str = "# sent_id = 1
+1-2\tdel\t_\t_\t_\t_\t_\t_\t_\tSpaceAfter=No
+1\tde\tde\tADP\t_\t_\t3\tcase\t3:case\t_
+2\tel\tel\tDET\t_\t_\t3\tdet\t3:det\t_
+2.1\tcome\tcomer\tVERB\t_\t_\t_\t_\t0:root\t_
+3\tperro\tperro\tNOUN\t_\t_\t0\troot\t0:root|2.1:nsubj\t_
+
+0.1\tes\tser\tAUX\t_\t_\t_\t_\t0:root\t_
+1\tHola\thola\tINTJ\t_\t_\t0\troot\t0:root\t_
+
+";
+
+    #[test]
+    fn multiword_tokens_and_empty_nodes_round_trip() {
+        let sentences = WordReader::new(DEL_PERRO.as_bytes())
+            .words()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(sentences.len(), 2);
+        assert_eq!(sentences[0].sentence.len(), 4);
+        assert_eq!(sentences[0].multiword_tokens[0].form, "del");
+        assert_eq!(sentences[0].empty_nodes[0].id(), "2.1");
+        assert_eq!(sentences[0].empty_nodes[0].deps.as_deref(), Some("0:root"));
+        assert_eq!(sentences[1].empty_nodes[0].word, 0);
+
+        let mut writer = WordWriter::new(Vec::new());
+        for words in &sentences {
+            writer.write_words(words).unwrap();
+        }
+
+        assert_eq!(String::from_utf8(writer.write).unwrap(), DEL_PERRO);
+    }
 }
@@ -1,24 +1,267 @@
 use std::borrow::Cow;
 
+use anyhow::{anyhow, bail, Result};
 use conllu::display::{ConlluFeatures, ConlluMisc};
+use itertools::Itertools;
+use udgraph::graph::Sentence;
 use udgraph::token::Token;
 
-pub type LayerCallback = Box<dyn Fn(&Token) -> Option<Cow<str>>>;
+/// Universal features (CoNLL 2018 shared task).
+static UNIVERSAL_FEATURES: &[&str] = &[
+    "Abbr", "Animacy", "Aspect", "Case", "Definite", "Degree", "Evident", "Foreign", "Gender",
+    "Mood", "NumType", "Number", "Person", "Polarity", "Polite", "Poss", "PronType", "Reflex",
+    "Tense", "VerbForm", "Voice",
+];
 
-pub fn layer_callback(layer: &str) -> Option<LayerCallback> {
+/// Form of the root in the `head-form` layer.
+pub static ROOT_FORM: &str = "<root>";
+
+/// Callback that extracts a layer value from the token at an index of a
+/// sentence.
+pub type LayerCallback = Box<dyn Fn(&Sentence, usize) -> Option<Cow<str>>>;
+
+/// Construct a layer callback from a callback that only needs the token.
+fn token_callback<F>(callback: F) -> LayerCallback
+where
+    F: 'static + Fn(&Token) -> Option<Cow<str>>,
+{
+    Box::new(move |sentence, idx| sentence[idx].token().and_then(&callback))
+}
+
+/// Get the callback for a basic layer.
+fn layer_callback(layer: &str) -> Option<LayerCallback> {
     match layer {
-        "features" => Some(Box::new(|t| {
+        "alltags" => Some(token_callback(|t| {
+            Some(Cow::Owned(format!(
+                "{} {} {}",
+                t.upos().unwrap_or("_"),
+                t.xpos().unwrap_or("_"),
+                universal_features_string(t)
+            )))
+        })),
+        "deprel" => Some(Box::new(|s, idx| {
+            s.dep_graph()
+                .head(idx)
+                .and_then(|triple| triple.relation().map(|r| Cow::Owned(r.to_owned())))
+        })),
+        "deps" => Some(token_callback(|t| t.deps().map(Cow::Borrowed))),
+        "features" => Some(token_callback(|t| {
             Some(Cow::Owned(
                 ConlluFeatures::borrowed(t.features()).to_string(),
             ))
         })),
-        "form" => Some(Box::new(|t| Some(Cow::Borrowed(t.form())))),
-        "lemma" => Some(Box::new(|t| t.lemma().map(Cow::Borrowed))),
-        "misc" => Some(Box::new(|t| {
+        "form" => Some(token_callback(|t| Some(Cow::Borrowed(t.form())))),
+        "head" => Some(Box::new(|s, idx| {
+            s.dep_graph()
+                .head(idx)
+                .map(|triple| Cow::Owned(triple.head().to_string()))
+        })),
+        "head-form" => Some(Box::new(|s, idx| {
+            let head = s.dep_graph().head(idx)?.head();
+            match s[head].token() {
+                Some(token) => Some(Cow::Borrowed(token.form())),
+                None => Some(Cow::Borrowed(ROOT_FORM)),
+            }
+        })),
+        "lemma" => Some(token_callback(|t| t.lemma().map(Cow::Borrowed))),
+        "misc" => Some(token_callback(|t| {
             Some(Cow::Owned(ConlluMisc::borrowed(t.misc()).to_string()))
         })),
-        "upos" => Some(Box::new(|t| t.upos().map(Cow::Borrowed))),
-        "xpos" => Some(Box::new(|t| t.xpos().map(Cow::Borrowed))),
+        "ufeats" => Some(token_callback(|t| Some(universal_features_string(t)))),
+        "upos" => Some(token_callback(|t| t.upos().map(Cow::Borrowed))),
+        "xpos" => Some(token_callback(|t| t.xpos().map(Cow::Borrowed))),
         _ => None,
     }
 }
+
+pub fn feature_callback(feature: impl Into<String>) -> LayerCallback {
+    let feature = feature.into();
+
+    token_callback(move |token| {
+        token
+            .features()
+            .get(&feature)
+            .map(|s| Cow::Borrowed(s.as_str()))
+    })
+}
+
+pub fn misc_callback(feature: impl Into<String>) -> LayerCallback {
+    let feature = feature.into();
+
+    token_callback(move |token| {
+        token
+            .misc()
+            .get(&feature)
+            .map(Option::as_ref)
+            .unwrap_or(None)
+            .map(|s| Cow::Borrowed(s.as_str()))
+    })
+}
+
+/// Parse a layer expression into a callback.
+///
+/// A layer expression is one of:
+///
+/// * a basic layer, such as `form` or `upos`;
+/// * a parameterized layer: `feature:NAME` or `misc:NAME`;
+/// * a function applied to an expression: `lowercase(EXPR)`;
+/// * a combination of expressions: `EXPR+EXPR`. The values of the
+///   combined expressions are joined with `|`, using `_` for missing
+///   values.
+pub fn parse_layer(expr: &str) -> Result<LayerCallback> {
+    if split_top_level(expr, ',')?.len() > 1 {
+        bail!(
+            "Only one layer expression is accepted, found a comma-separated list: {}",
+            expr
+        );
+    }
+
+    let mut parts = split_top_level(expr, '+')?
+        .into_iter()
+        .map(parse_layer_term)
+        .collect::<Result<Vec<_>>>()?;
+
+    if parts.len() == 1 {
+        return Ok(parts.remove(0));
+    }
+
+    Ok(Box::new(move |sentence, idx| {
+        let values = parts
+            .iter()
+            .map(|part| part(sentence, idx))
+            .collect::<Vec<_>>();
+        if values.iter().all(Option::is_none) {
+            return None;
+        }
+
+        Some(Cow::Owned(
+            values
+                .iter()
+                .map(|value| value.as_deref().unwrap_or("_"))
+                .join("|"),
+        ))
+    }))
+}
+
+/// Parse a layer expression that is not a combination.
+fn parse_layer_term(term: &str) -> Result<LayerCallback> {
+    let term = term.trim();
+    if term.is_empty() {
+        bail!("Empty layer expression");
+    }
+
+    if let Some(open) = term.find('(') {
+        if !term.ends_with(')') {
+            bail!("Missing closing parenthesis in layer expression: {}", term);
+        }
+
+        let function = &term[..open];
+        let callback = parse_layer(&term[open + 1..term.len() - 1])?;
+        return match function {
+            "lowercase" => Ok(Box::new(move |sentence, idx| {
+                callback(sentence, idx).map(|value| Cow::Owned(value.to_lowercase()))
+            })),
+            _ => bail!("Unknown layer function: {}", function),
+        };
+    }
+
+    if let Some((layer, param)) = term.split_once(':') {
+        if param.is_empty() {
+            bail!("Missing parameter in layer expression: {}", term);
+        }
+
+        return match layer {
+            "feature" => Ok(feature_callback(param)),
+            "misc" => Ok(misc_callback(param)),
+            _ => bail!("Unknown parameterized layer: {}", layer),
+        };
+    }
+
+    match term {
+        "feature" | "misc" => bail!("Layer {} requires a parameter, e.g. {}:NAME", term, term),
+        _ => layer_callback(term).ok_or_else(|| anyhow!("Unknown layer: {}", term)),
+    }
+}
+
+/// Split an expression on a separator that does not occur within
+/// parentheses.
+fn split_top_level(expr: &str, separator: char) -> Result<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (idx, c) in expr.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth.checked_sub(1).ok_or_else(|| {
+                    anyhow!("Unbalanced parentheses in layer expression: {}", expr)
+                })?
+            }
+            c if c == separator && depth == 0 => {
+                parts.push(&expr[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+
+    if depth != 0 {
+        bail!("Unbalanced parentheses in layer expression: {}", expr);
+    }
+
+    parts.push(&expr[start..]);
+
+    Ok(parts)
+}
+
+/// Get the callbacks for a comma-separated list of layer expressions,
+/// paired with the expressions.
+pub fn named_layer_callbacks(layers: &str) -> Result<Vec<(String, LayerCallback)>> {
+    split_top_level(layers, ',')?
+        .into_iter()
+        .map(|layer| Ok((layer.trim().to_owned(), parse_layer(layer)?)))
+        .collect()
+}
+
+/// Get the callbacks for a comma-separated list of morphological features,
+/// paired with the layer names.
+pub fn named_feature_callbacks(features: &str) -> Vec<(String, LayerCallback)> {
+    features
+        .split(',')
+        .map(|feature| (format!("feature:{}", feature), feature_callback(feature)))
+        .collect()
+}
+
+/// Get the callbacks for a comma-separated list of miscellaneous features,
+/// paired with the layer names.
+pub fn named_misc_callbacks(misc: &str) -> Vec<(String, LayerCallback)> {
+    misc.split(',')
+        .map(|misc| (format!("misc:{}", misc), misc_callback(misc)))
+        .collect()
+}
+
+/// Get the universal features of a token.
+pub fn universal_features(token: &Token) -> Vec<(&str, &str)> {
+    token
+        .features()
+        .iter()
+        .filter(|(feature, _)| UNIVERSAL_FEATURES.contains(&feature.as_str()))
+        .map(|(feature, value)| (feature.as_str(), value.as_str()))
+        .collect()
+}
+
+/// Format the universal features of a token as a CoNLL-U feature string.
+fn universal_features_string(token: &Token) -> Cow<'_, str> {
+    let features = universal_features(token);
+    if features.is_empty() {
+        Cow::Borrowed("_")
+    } else {
+        Cow::Owned(
+            features
+                .iter()
+                .map(|(feature, value)| format!("{}={}", feature, value))
+                .join("|"),
+        )
+    }
+}
@@ -3,13 +3,19 @@ use std::io::stdout;
 use anyhow::Result;
 use clap::{crate_version, App, AppSettings, Arg, Shell, SubCommand};
 
+pub mod align;
+pub mod arcs;
+pub mod eval;
+pub mod html;
 pub mod io;
 pub mod layer;
+pub mod normalize;
+pub mod report;
 pub mod subcommands;
 pub mod traits;
 pub mod unicode;
 
-use traits::ConlluApp;
+use traits::{compression_args, ConlluApp};
 
 static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
     AppSettings::DontCollapseArgsInUsage,
@@ -28,20 +34,23 @@ fn main() -> Result<()> {
         subcommands::MergeApp::app(),
         subcommands::PartitionApp::app(),
         subcommands::ShuffleApp::app(),
+        subcommands::SignificanceApp::app(),
         subcommands::ToTextApp::app(),
     ];
 
-    let cli = App::new("conllu")
-        .settings(DEFAULT_CLAP_SETTINGS)
-        .about("CoNLL-U utilities")
-        .version(crate_version!())
-        .subcommands(apps)
-        .subcommand(
-            SubCommand::with_name("completions")
-                .about("Generate completion scripts for your shell")
-                .setting(AppSettings::ArgRequiredElseHelp)
-                .arg(Arg::with_name("shell").possible_values(&Shell::variants())),
-        );
+    let cli = compression_args(
+        App::new("conllu")
+            .settings(DEFAULT_CLAP_SETTINGS)
+            .about("CoNLL-U utilities")
+            .version(crate_version!()),
+    )
+    .subcommands(apps)
+    .subcommand(
+        SubCommand::with_name("completions")
+            .about("Generate completion scripts for your shell")
+            .setting(AppSettings::ArgRequiredElseHelp)
+            .arg(Arg::with_name("shell").possible_values(&Shell::variants())),
+    );
     let matches = cli.clone().get_matches();
 
     match matches.subcommand_name().unwrap() {
@@ -76,6 +85,10 @@ fn main() -> Result<()> {
             subcommands::PartitionApp::parse(matches.subcommand_matches("partition").unwrap())?
                 .run()
         }
+        "significance" => subcommands::SignificanceApp::parse(
+            matches.subcommand_matches("significance").unwrap(),
+        )?
+        .run(),
         "shuffle" => {
             subcommands::ShuffleApp::parse(matches.subcommand_matches("shuffle").unwrap())?.run()
         }
@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+
+use crate::layer::LayerCallback;
+use crate::unicode::{normalize, Normalization};
+
+/// Normalization of layer values before comparison.
+///
+/// Values are normalized in the following order: Unicode normalization,
+/// lowercasing, and finally mapping.
+pub struct ValueNormalizer {
+    lowercase: bool,
+    mapping: Vec<(String, String)>,
+    normalized_mapping: OnceCell<HashMap<String, String>>,
+    unicode: Normalization,
+}
+
+impl Default for ValueNormalizer {
+    fn default() -> Self {
+        ValueNormalizer {
+            lowercase: false,
+            mapping: Vec::new(),
+            normalized_mapping: OnceCell::new(),
+            unicode: Normalization::None,
+        }
+    }
+}
+
+impl ValueNormalizer {
+    /// Compare values case-insensitively.
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self.normalized_mapping = OnceCell::new();
+        self
+    }
+
+    /// Map values using a mapping file.
+    ///
+    /// Each line of the mapping file contains a value and its replacement,
+    /// separated by a tab. Empty lines and lines starting with `#` are
+    /// ignored. Since mapping is the last normalization step, the values
+    /// and replacements are Unicode-normalized and lowercased when the
+    /// normalizer is first used, regardless of the order in which the
+    /// normalizer was configured.
+    pub fn mapping_file(mut self, path: &str) -> Result<Self> {
+        let file = File::open(path).context(format!("Cannot open mapping file: {}", path))?;
+
+        for (line_idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.context(format!("Cannot read line from mapping file: {}", path))?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_once('\t') {
+                Some((value, replacement)) => self
+                    .mapping
+                    .push((value.to_owned(), replacement.to_owned())),
+                None => bail!(
+                    "Mapping without replacement on line {} of {}: {}",
+                    line_idx + 1,
+                    path,
+                    line
+                ),
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Apply Unicode normalization.
+    pub fn unicode(mut self, unicode: Normalization) -> Self {
+        self.unicode = unicode;
+        self.normalized_mapping = OnceCell::new();
+        self
+    }
+
+    /// Check whether the normalizer leaves all values unchanged.
+    pub fn is_identity(&self) -> bool {
+        !self.lowercase && self.mapping.is_empty() && matches!(self.unicode, Normalization::None)
+    }
+
+    pub fn normalize<'a>(&self, value: Cow<'a, str>) -> Cow<'a, str> {
+        let value = self.normalize_unmapped(value);
+
+        match self.normalized_mapping().get(value.as_ref()) {
+            Some(replacement) => Cow::Owned(replacement.clone()),
+            None => value,
+        }
+    }
+
+    /// Get the mapping with normalized values and replacements.
+    fn normalized_mapping(&self) -> &HashMap<String, String> {
+        self.normalized_mapping.get_or_init(|| {
+            self.mapping
+                .iter()
+                .map(|(value, replacement)| {
+                    (
+                        self.normalize_unmapped(Cow::Borrowed(value)).into_owned(),
+                        self.normalize_unmapped(Cow::Borrowed(replacement))
+                            .into_owned(),
+                    )
+                })
+                .collect()
+        })
+    }
+
+    /// Apply Unicode normalization and lowercasing.
+    fn normalize_unmapped<'a>(&self, value: Cow<'a, str>) -> Cow<'a, str> {
+        let value = match self.unicode {
+            Normalization::None => value,
+            unicode => Cow::Owned(normalize(&value, unicode)),
+        };
+
+        if self.lowercase {
+            Cow::Owned(value.to_lowercase())
+        } else {
+            value
+        }
+    }
+}
+
+/// Normalize the values of layer callbacks.
+///
+/// The callbacks are returned unchanged when the normalizer does not
+/// change values.
+pub fn normalize_callbacks(
+    callbacks: Vec<(String, LayerCallback)>,
+    normalizer: ValueNormalizer,
+) -> Vec<(String, LayerCallback)> {
+    if normalizer.is_identity() {
+        return callbacks;
+    }
+
+    let normalizer = Rc::new(normalizer);
+
+    callbacks
+        .into_iter()
+        .map(|(name, callback)| {
+            let normalizer = normalizer.clone();
+            let callback: LayerCallback = Box::new(move |sentence, idx| {
+                callback(sentence, idx).map(|value| normalizer.normalize(value))
+            });
+            (name, callback)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::fs;
+
+    use super::ValueNormalizer;
+
+    #[test]
+    fn mapping_does_not_depend_on_configuration_order() {
+        let path = std::env::temp_dir().join(format!("conllu-mapping-{}.tsv", std::process::id()));
+        fs::write(&path, "Colour\tColor\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let mapping_first = ValueNormalizer::default()
+            .mapping_file(path)
+            .unwrap()
+            .lowercase(true);
+        let mapping_last = ValueNormalizer::default()
+            .lowercase(true)
+            .mapping_file(path)
+            .unwrap();
+
+        for normalizer in &[mapping_first, mapping_last] {
+            assert_eq!(normalizer.normalize(Cow::Borrowed("COLOUR")), "color");
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+}
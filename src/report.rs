@@ -0,0 +1,452 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+/// Output formats of evaluation results.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    Json,
+    Text,
+    Tsv,
+}
+
+pub fn output_format_from<S>(value: S) -> Option<OutputFormat>
+where
+    S: AsRef<str>,
+{
+    match value.as_ref() {
+        "json" => Some(OutputFormat::Json),
+        "text" => Some(OutputFormat::Text),
+        "tsv" => Some(OutputFormat::Tsv),
+        _ => None,
+    }
+}
+
+/// An evaluation result.
+///
+/// Scores are percentages.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum Record {
+    /// Accuracy of a metric.
+    Accuracy {
+        metric: String,
+        score: f64,
+        correct: usize,
+        total: usize,
+    },
+
+    /// Precision, recall and F1 of a metric, optionally for a single label.
+    F1 {
+        metric: String,
+        label: Option<String>,
+        f1: f64,
+        precision: f64,
+        recall: f64,
+        correct: usize,
+        gold: usize,
+        predicted: usize,
+    },
+
+    /// Precision, recall and F1 of a bucket of a breakdown.
+    ///
+    /// Gold and predicted tokens can be in different buckets, so the
+    /// correct gold and predicted tokens are counted separately.
+    Bucket {
+        metric: String,
+        label: String,
+        f1: f64,
+        precision: f64,
+        recall: f64,
+        gold: usize,
+        gold_correct: usize,
+        predicted: usize,
+        predicted_correct: usize,
+    },
+
+    /// Score of a metric aggregated over folds.
+    ///
+    /// The micro-average is computed from the pooled counts of all folds,
+    /// the macro-average and (sample) standard deviation from the scores
+    /// of the folds.
+    Aggregate {
+        metric: String,
+        micro_average: f64,
+        macro_average: f64,
+        std_dev: f64,
+        folds: usize,
+    },
+
+    /// Significance of the difference between the scores of two systems.
+    ///
+    /// The bounds are those of the bootstrap confidence intervals.
+    Significance {
+        metric: String,
+        test: String,
+        score_a: f64,
+        score_a_low: f64,
+        score_a_high: f64,
+        score_b: f64,
+        score_b_low: f64,
+        score_b_high: f64,
+        difference: f64,
+        difference_low: f64,
+        difference_high: f64,
+        p_value: f64,
+    },
+
+    /// Score of a metric for a single sentence.
+    ///
+    /// The sentence is identified by its `sent_id` or, when absent, by its
+    /// 1-based position in the treebank.
+    Sentence {
+        sentence: String,
+        length: usize,
+        metric: String,
+        score: f64,
+        correct: usize,
+        total: usize,
+    },
+
+    /// An evaluation setting, such as the punctuation policy.
+    Setting { name: String, value: String },
+
+    /// Number of times that a gold value was predicted as another value.
+    Confusion {
+        metric: String,
+        gold: String,
+        predicted: String,
+        count: usize,
+    },
+}
+
+impl Record {
+    /// Construct an accuracy record.
+    pub fn accuracy(metric: impl Into<String>, correct: usize, total: usize) -> Self {
+        Record::Accuracy {
+            metric: metric.into(),
+            score: percentage(correct, total),
+            correct,
+            total,
+        }
+    }
+
+    /// Construct a precision/recall/F1 record.
+    pub fn f1(
+        metric: impl Into<String>,
+        label: Option<String>,
+        correct: usize,
+        gold: usize,
+        predicted: usize,
+    ) -> Self {
+        let precision = percentage(correct, predicted);
+        let recall = percentage(correct, gold);
+        let f1 = f1_score(precision, recall);
+
+        Record::F1 {
+            metric: metric.into(),
+            label,
+            f1,
+            precision,
+            recall,
+            correct,
+            gold,
+            predicted,
+        }
+    }
+
+    /// Construct a breakdown bucket record.
+    pub fn bucket(
+        metric: impl Into<String>,
+        label: impl Into<String>,
+        gold: usize,
+        gold_correct: usize,
+        predicted: usize,
+        predicted_correct: usize,
+    ) -> Self {
+        let precision = percentage(predicted_correct, predicted);
+        let recall = percentage(gold_correct, gold);
+        let f1 = f1_score(precision, recall);
+
+        Record::Bucket {
+            metric: metric.into(),
+            label: label.into(),
+            f1,
+            precision,
+            recall,
+            gold,
+            gold_correct,
+            predicted,
+            predicted_correct,
+        }
+    }
+
+    /// Construct a confusion record.
+    pub fn confusion(metric: impl Into<String>, gold: &str, predicted: &str, count: usize) -> Self {
+        Record::Confusion {
+            metric: metric.into(),
+            gold: gold.to_owned(),
+            predicted: predicted.to_owned(),
+            count,
+        }
+    }
+
+    /// Construct a sentence score record.
+    pub fn sentence(
+        sentence: impl Into<String>,
+        length: usize,
+        metric: impl Into<String>,
+        score: f64,
+        correct: usize,
+        total: usize,
+    ) -> Self {
+        Record::Sentence {
+            sentence: sentence.into(),
+            length,
+            metric: metric.into(),
+            score,
+            correct,
+            total,
+        }
+    }
+
+    /// Construct a setting record.
+    pub fn setting(name: impl Into<String>, value: impl ToString) -> Self {
+        Record::Setting {
+            name: name.into(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Get the metric of the record, if it has one.
+    pub fn metric_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Record::Accuracy { metric, .. }
+            | Record::F1 { metric, .. }
+            | Record::Bucket { metric, .. }
+            | Record::Aggregate { metric, .. }
+            | Record::Significance { metric, .. }
+            | Record::Sentence { metric, .. }
+            | Record::Confusion { metric, .. } => Some(metric),
+            Record::Setting { .. } => None,
+        }
+    }
+
+    /// Format the record as tab-separated values. The first column is
+    /// the record type.
+    fn to_tsv(&self) -> String {
+        match self {
+            Record::Accuracy {
+                metric,
+                score,
+                correct,
+                total,
+            } => format!("accuracy\t{}\t{:.2}\t{}\t{}", metric, score, correct, total),
+            Record::F1 {
+                metric,
+                label,
+                f1,
+                precision,
+                recall,
+                correct,
+                gold,
+                predicted,
+            } => format!(
+                "f1\t{}\t{}\t{:.2}\t{:.2}\t{:.2}\t{}\t{}\t{}",
+                metric,
+                label.as_deref().unwrap_or("_"),
+                f1,
+                precision,
+                recall,
+                correct,
+                gold,
+                predicted
+            ),
+            Record::Bucket {
+                metric,
+                label,
+                f1,
+                precision,
+                recall,
+                gold,
+                gold_correct,
+                predicted,
+                predicted_correct,
+            } => format!(
+                "bucket\t{}\t{}\t{:.2}\t{:.2}\t{:.2}\t{}\t{}\t{}\t{}",
+                metric,
+                label,
+                f1,
+                precision,
+                recall,
+                gold,
+                gold_correct,
+                predicted,
+                predicted_correct
+            ),
+            Record::Aggregate { .. } => format!("aggregate\t{}", self.to_text()),
+            Record::Significance { test, .. } => {
+                format!("significance\t{}\t{}", test, self.to_text())
+            }
+            Record::Sentence { .. } => format!("sentence\t{}", self.to_text()),
+            Record::Setting { name, value } => format!("setting\t{}\t{}", name, value),
+            Record::Confusion {
+                metric,
+                gold,
+                predicted,
+                count,
+            } => format!("confusion\t{}\t{}\t{}\t{}", metric, gold, predicted, count),
+        }
+    }
+
+    /// Format the record as human-readable text.
+    ///
+    /// Records with a label are formatted as table rows, starting with
+    /// the label.
+    fn to_text(&self) -> String {
+        match self {
+            Record::Accuracy {
+                metric,
+                score,
+                correct,
+                total,
+            } => format!("{}\t{:.2}\t{}\t{}", metric, score, correct, total),
+            Record::F1 {
+                metric,
+                label: None,
+                f1,
+                precision,
+                recall,
+                correct,
+                gold,
+                predicted,
+            } => format!(
+                "{}\t{:.2}\t{:.2}\t{:.2}\t{}\t{}\t{}",
+                metric, f1, precision, recall, correct, gold, predicted
+            ),
+            Record::F1 {
+                label: Some(label),
+                f1,
+                precision,
+                recall,
+                correct,
+                gold,
+                predicted,
+                ..
+            } => format!(
+                "{}\t{}\t{}\t{}\t{:.2}\t{:.2}\t{:.2}",
+                label, gold, predicted, correct, precision, recall, f1
+            ),
+            Record::Bucket {
+                label,
+                f1,
+                precision,
+                recall,
+                gold,
+                gold_correct,
+                predicted,
+                predicted_correct,
+                ..
+            } => format!(
+                "{}\t{}\t{}\t{}\t{}\t{:.2}\t{:.2}\t{:.2}",
+                label, gold, predicted, gold_correct, predicted_correct, precision, recall, f1
+            ),
+            Record::Aggregate {
+                metric,
+                micro_average,
+                macro_average,
+                std_dev,
+                folds,
+            } => format!(
+                "{}\t{:.2}\t{:.2}\t{:.2}\t{}",
+                metric, micro_average, macro_average, std_dev, folds
+            ),
+            Record::Significance {
+                metric,
+                score_a,
+                score_a_low,
+                score_a_high,
+                score_b,
+                score_b_low,
+                score_b_high,
+                difference,
+                difference_low,
+                difference_high,
+                p_value,
+                ..
+            } => format!(
+                "{}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{:.2}\t{:.4}",
+                metric,
+                score_a,
+                score_a_low,
+                score_a_high,
+                score_b,
+                score_b_low,
+                score_b_high,
+                difference,
+                difference_low,
+                difference_high,
+                p_value
+            ),
+            Record::Sentence {
+                sentence,
+                length,
+                metric,
+                score,
+                correct,
+                total,
+            } => format!(
+                "{}\t{}\t{}\t{:.2}\t{}\t{}",
+                sentence, length, metric, score, correct, total
+            ),
+            Record::Setting { name, value } => format!("{}\t{}", name, value),
+            Record::Confusion {
+                gold,
+                predicted,
+                count,
+                ..
+            } => format!("{}\t{}\t{}", gold, predicted, count),
+        }
+    }
+}
+
+/// Write records in the given format.
+///
+/// JSON output has one record per line, TSV output one record per row,
+/// so that both can be appended to a results log.
+pub fn write_records(
+    mut writer: impl Write,
+    format: OutputFormat,
+    records: &[Record],
+) -> io::Result<()> {
+    for record in records {
+        match format {
+            OutputFormat::Json => {
+                serde_json::to_writer(&mut writer, record)?;
+                writeln!(writer)?;
+            }
+            OutputFormat::Text => writeln!(writer, "{}", record.to_text())?,
+            OutputFormat::Tsv => writeln!(writer, "{}", record.to_tsv())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Compute the F1 score from precision and recall.
+pub fn f1_score(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0. {
+        0.
+    } else {
+        2. * precision * recall / (precision + recall)
+    }
+}
+
+/// Compute a percentage, returning zero when the total is zero.
+pub fn percentage(correct: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.
+    } else {
+        (100. * correct as f64) / total as f64
+    }
+}
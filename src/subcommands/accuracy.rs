@@ -1,327 +1,773 @@
-use std::borrow::Cow;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{stdout, BufRead, BufWriter, Write};
+use std::path::Path;
 
-use anyhow::{bail, ensure, format_err, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use clap::{App, Arg, ArgGroup, ArgMatches};
-use conllu::display::ConlluSentence;
-use conllu::io::Reader;
-use conllu::Error;
-use udgraph::graph::{Node, Sentence};
-use unicode_categories::UnicodeCategories;
-
-use crate::layer::{layer_callback, LayerCallback};
-use crate::traits::ConlluApp;
-
+use conllu::io::ReadSentence;
+use itertools::Itertools;
+use udgraph::graph::{Comment, Sentence};
+
+use crate::align::{
+    align_treebanks, match_treebanks, sentence_id, Alignment, SentenceIter, SentencePair,
+};
+use crate::eval::{
+    aggregate_records, parse_edges, AttachmentEvaluator, Breakdown, BreakdownEvaluator,
+    EnhancedEvaluator, Evaluator, F1Counts, LayerEvaluator, PunctuationPolicy, Vocabulary,
+    VocabularyEvaluator, VocabularyLayer,
+};
+use crate::io::{open_word_reader, open_word_writer, OutputCompression, WordReader, Words};
+use crate::layer::{
+    named_feature_callbacks, named_layer_callbacks, named_misc_callbacks, LayerCallback,
+};
+use crate::normalize::normalize_callbacks;
+use crate::report::{output_format_from, write_records, OutputFormat, Record};
+use crate::traits::{output_compression, ConlluApp, ConlluMatchingApp, ConlluNormalizingApp};
+
+const ALIGN: &str = "ALIGN";
+const ANNOTATE: &str = "ANNOTATE";
+const ARC_LENGTH_EDGES: &str = "ARC_LENGTH_EDGES";
 const ATTACHMENT_SCORES: &str = "ATTACHMENT_SCORES";
+const BREAKDOWN: &str = "BREAKDOWN";
+const CONFUSION_MATRIX: &str = "CONFUSION_MATRIX";
 const DEFAULT: &str = "DEFAULT";
+const ENHANCED: &str = "ENHANCED";
+const EXACT_MATCH: &str = "EXACT_MATCH";
 const FEATURE: &str = "FEATURE";
+const FOLD: &str = "FOLD";
 const GOLD_TREEBANK: &str = "GOLD_TREEBANK";
 const LAYER: &str = "LAYER";
 const MISC: &str = "MISC";
+const OUTPUT_FORMAT: &str = "OUTPUT_FORMAT";
+const PER_RELATION: &str = "PER_RELATION";
 const PREDICTED_TREEBANK: &str = "PREDICTED_TREEBANK";
-
-pub enum Evaluation {
-    Callbacks(Vec<LayerCallback>),
-    AttachmentScore,
-}
+const PUNCTUATION: &str = "PUNCTUATION";
+const PUNCTUATION_REGEX: &str = "PUNCTUATION_REGEX";
+const ROOT_DISTANCE_EDGES: &str = "ROOT_DISTANCE_EDGES";
+const SENTENCE_LENGTH_EDGES: &str = "SENTENCE_LENGTH_EDGES";
+const SENTENCE_SCORES: &str = "SENTENCE_SCORES";
+const TOP_CONFUSIONS: &str = "TOP_CONFUSIONS";
+const TRAINING_TREEBANK: &str = "TRAINING_TREEBANK";
+const UD_METRICS: &str = "UD_METRICS";
+const UNIVERSAL_RELATIONS: &str = "UNIVERSAL_RELATIONS";
+const VOCABULARY: &str = "VOCABULARY";
 
 pub struct AccuracyApp {
+    align: bool,
+    annotate: Option<String>,
+    compression: OutputCompression,
+    attachment_scores: bool,
+    breakdowns: Vec<Breakdown>,
+    confusion_matrix: bool,
     default: Option<String>,
-    evaluation: Evaluation,
-    gold_treebank: String,
-    predicted_treebank: String,
+    enhanced: bool,
+    exact_match: bool,
+    folds: Vec<(String, String)>,
+    layer_callbacks: Vec<(String, LayerCallback)>,
+    match_sent_id: bool,
+    output_format: OutputFormat,
+    per_relation: bool,
+    punctuation: PunctuationPolicy,
+    sentence_scores: Option<String>,
+    top_confusions: Option<usize>,
+    training_treebank: Option<String>,
+    ud_metrics: bool,
+    universal_relations: bool,
+    vocabulary_layer: VocabularyLayer,
 }
 
+impl ConlluMatchingApp for AccuracyApp {}
+
+impl ConlluNormalizingApp for AccuracyApp {}
+
 impl ConlluApp for AccuracyApp {
     fn app() -> App<'static, 'static> {
-        App::new("accuracy")
-            .about("Compute the accuracy of a layer")
-            .arg(
-                Arg::with_name(GOLD_TREEBANK)
-                    .help("Gold standard treebank")
-                    .required(true),
-            )
-            .arg(
-                Arg::with_name(PREDICTED_TREEBANK)
-                    .help("Non-gold standard treebank")
-                    .required(true),
-            )
-            .arg(
-                Arg::with_name(ATTACHMENT_SCORES)
-                    .short("a")
-                    .long("attachment")
-                    .help("Default value when no value is present"),
-            )
-            .arg(
-                Arg::with_name(DEFAULT)
-                    .short("d")
-                    .long("default")
-                    .takes_value(true)
-                    .conflicts_with(ATTACHMENT_SCORES)
-                    .help("Default value when no value is present"),
-            )
-            .arg(
-                Arg::with_name(LAYER)
-                    .short("l")
-                    .long("layer")
-                    .takes_value(true)
-                    .help("Evaluate a layer"),
-            )
-            .arg(
-                Arg::with_name(FEATURE)
-                    .short("f")
-                    .long("feature")
-                    .takes_value(true)
-                    .help("Evaluate a morphological feature"),
-            )
-            .arg(
-                Arg::with_name(MISC)
-                    .short("m")
-                    .long("misc")
-                    .takes_value(true)
-                    .help("Evaluate miscellaneous feature"),
-            )
-            .group(
-                ArgGroup::with_name("source")
-                    .args(&[ATTACHMENT_SCORES, FEATURE, LAYER, MISC])
-                    .required(true),
-            )
+        Self::matching_args(Self::normalizing_args(
+            App::new("accuracy")
+                .about("Compute the accuracy of layers and attachment")
+                .arg(
+                    Arg::with_name(GOLD_TREEBANK)
+                        .help("Gold standard treebank (or glob pattern)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(PREDICTED_TREEBANK)
+                        .help("Non-gold standard treebank (or glob pattern)")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(FOLD)
+                        .short("F")
+                        .long("fold")
+                        .value_names(&["GOLD", "PREDICTED"])
+                        .number_of_values(2)
+                        .multiple(true)
+                        .help("Evaluate an additional fold (treebanks or glob patterns)"),
+                )
+                .arg(
+                    Arg::with_name(ALIGN)
+                        .short("A")
+                        .long("align")
+                        .conflicts_with(Self::MATCH_SENT_ID)
+                        .help("Align tokens by character offsets to evaluate tokenization"),
+                )
+                .arg(
+                    Arg::with_name(ATTACHMENT_SCORES)
+                        .short("a")
+                        .long("attachment")
+                        .help("Evaluate attachment scores"),
+                )
+                .arg(
+                    Arg::with_name(ENHANCED)
+                        .short("e")
+                        .long("enhanced")
+                        .help("Evaluate enhanced dependencies (ELAS and EULAS)"),
+                )
+                .arg(
+                    Arg::with_name(EXACT_MATCH)
+                        .short("x")
+                        .long("exact-match")
+                        .help("Report the percentage of sentences in which all tokens are correct"),
+                )
+                .arg(
+                    Arg::with_name(DEFAULT)
+                        .short("d")
+                        .long("default")
+                        .takes_value(true)
+                        .help("Default value when no value is present"),
+                )
+                .arg(
+                    Arg::with_name(LAYER)
+                        .short("l")
+                        .long("layer")
+                        .takes_value(true)
+                        .help("Evaluate layers (comma-separated layer expressions)"),
+                )
+                .arg(
+                    Arg::with_name(FEATURE)
+                        .short("f")
+                        .long("feature")
+                        .takes_value(true)
+                        .help("Evaluate morphological features (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(MISC)
+                        .short("m")
+                        .long("misc")
+                        .takes_value(true)
+                        .help("Evaluate miscellaneous features (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(PER_RELATION)
+                        .short("r")
+                        .long("per-relation")
+                        .requires("dependencies")
+                        .help("Report precision, recall and F1 per (enhanced) dependency relation"),
+                )
+                .arg(
+                    Arg::with_name(UD_METRICS)
+                        .short("u")
+                        .long("ud-metrics")
+                        .requires(ATTACHMENT_SCORES)
+                        .help("Report the CLAS, MLAS and BLEX metrics"),
+                )
+                .arg(
+                    Arg::with_name(UNIVERSAL_RELATIONS)
+                        .long("universal-relations")
+                        .requires(ATTACHMENT_SCORES)
+                        .help(
+                            "Also report LAS comparing only universal relations (without subtypes)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name(PUNCTUATION)
+                        .short("p")
+                        .long("punctuation")
+                        .value_name("POLICY")
+                        .possible_values(&["deprel", "form", "regex", "upos", "upos-sym"])
+                        .default_value("form")
+                        .help("Policy for excluding punctuation from attachment scores"),
+                )
+                .arg(
+                    Arg::with_name(PUNCTUATION_REGEX)
+                        .long("punctuation-regex")
+                        .value_name("REGEX")
+                        .takes_value(true)
+                        .required_if(PUNCTUATION, "regex")
+                        .help("Regular expression matching punctuation forms"),
+                )
+                .arg(
+                    Arg::with_name(BREAKDOWN)
+                        .short("b")
+                        .long("breakdown")
+                        .value_name("BREAKDOWNS")
+                        .takes_value(true)
+                        .requires(ATTACHMENT_SCORES)
+                        .help(
+                            "Break down attachment scores (comma-separated): \
+                         arc-length, direction, root-distance, sentence-length",
+                        ),
+                )
+                .arg(
+                    Arg::with_name(ARC_LENGTH_EDGES)
+                        .long("arc-length-edges")
+                        .value_name("EDGES")
+                        .default_value("1,2,3,4,5,6,7,8,10,15,20")
+                        .help("Lower bounds of arc length buckets (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(ROOT_DISTANCE_EDGES)
+                        .long("root-distance-edges")
+                        .value_name("EDGES")
+                        .default_value("1,2,3,4,5,6,7,8,10")
+                        .help("Lower bounds of root distance buckets (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(SENTENCE_LENGTH_EDGES)
+                        .long("sentence-length-edges")
+                        .value_name("EDGES")
+                        .default_value("1,11,21,31,41,51")
+                        .help("Lower bounds of sentence length buckets (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(CONFUSION_MATRIX)
+                        .short("c")
+                        .long("confusion-matrix")
+                        .requires("layers")
+                        .help("Print the confusion matrix (gold x predicted) as TSV"),
+                )
+                .arg(
+                    Arg::with_name(TOP_CONFUSIONS)
+                        .short("t")
+                        .long("top-confusions")
+                        .value_name("N")
+                        .takes_value(true)
+                        .requires("layers")
+                        .help("Print the N most frequent confusions"),
+                )
+                .arg(
+                    Arg::with_name(TRAINING_TREEBANK)
+                        .long("train")
+                        .value_name("TREEBANK")
+                        .takes_value(true)
+                        .help("Report scores for in-vocabulary and out-of-vocabulary tokens"),
+                )
+                .arg(
+                    Arg::with_name(VOCABULARY)
+                        .long("vocabulary")
+                        .value_name("LAYER")
+                        .possible_values(&["form", "lemma"])
+                        .requires(TRAINING_TREEBANK)
+                        .help("Layer of the training treebank vocabulary [default: form]"),
+                )
+                .arg(
+                    Arg::with_name(SENTENCE_SCORES)
+                        .short("s")
+                        .long("sentence-scores")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .help("Write the scores of every sentence to a file"),
+                )
+                .arg(
+                    Arg::with_name(ANNOTATE)
+                        .long("annotate")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .conflicts_with(ALIGN)
+                        .help("Write the predicted treebank with sentence scores in comments"),
+                )
+                .arg(
+                    Arg::with_name(OUTPUT_FORMAT)
+                        .short("o")
+                        .long("output-format")
+                        .value_name("FORMAT")
+                        .possible_values(&["json", "text", "tsv"])
+                        .default_value("text")
+                        .help("Output format"),
+                )
+                .group(
+                    ArgGroup::with_name("source")
+                        .args(&[ATTACHMENT_SCORES, ENHANCED, FEATURE, LAYER, MISC])
+                        .multiple(true)
+                        .required(true),
+                )
+                .group(
+                    ArgGroup::with_name("dependencies")
+                        .args(&[ATTACHMENT_SCORES, ENHANCED])
+                        .multiple(true),
+                )
+                .group(
+                    ArgGroup::with_name("layers")
+                        .args(&[FEATURE, LAYER, MISC])
+                        .multiple(true),
+                ),
+        ))
     }
 
     fn parse(matches: &ArgMatches) -> Result<Self> {
-        let gold_treebank = matches.value_of(GOLD_TREEBANK).unwrap().to_owned();
-        let predicted_treebank = matches.value_of(PREDICTED_TREEBANK).unwrap().to_owned();
+        let mut folds = expand_fold(
+            matches.value_of(GOLD_TREEBANK).unwrap(),
+            matches.value_of(PREDICTED_TREEBANK).unwrap(),
+        )?;
+        if let Some(fold_values) = matches.values_of(FOLD) {
+            for (gold, predicted) in fold_values.tuples() {
+                folds.extend(expand_fold(gold, predicted)?);
+            }
+        }
 
         let default = matches.value_of(DEFAULT).map(|s| s.to_owned());
 
-        let evaluation = match (
-            matches.is_present(ATTACHMENT_SCORES),
-            matches.value_of(LAYER),
-            matches.value_of(FEATURE),
-            matches.value_of(MISC),
-        ) {
-            (false, Some(layer), None, None) => Evaluation::Callbacks(
-                process_layer_callbacks(layer).context("Cannot parse layer(s)")?,
-            ),
-            (false, None, Some(feature), None) => {
-                Evaluation::Callbacks(vec![feature_callback(feature)])
-            }
-            (false, None, None, Some(misc)) => Evaluation::Callbacks(vec![misc_callback(misc)]),
-            (true, None, None, None) => Evaluation::AttachmentScore,
-            _ => unreachable!(),
+        let output_format = matches.value_of(OUTPUT_FORMAT).unwrap();
+        let output_format = output_format_from(output_format)
+            .context(format!("Unknown output format: {}", output_format))?;
+
+        let confusion_matrix = matches.is_present(CONFUSION_MATRIX);
+        let top_confusions = matches
+            .value_of(TOP_CONFUSIONS)
+            .map(|n| {
+                n.parse()
+                    .context(format!("Cannot parse number of confusions: {}", n))
+            })
+            .transpose()?;
+
+        let punctuation = PunctuationPolicy::from_name(
+            matches.value_of(PUNCTUATION).unwrap(),
+            matches.value_of(PUNCTUATION_REGEX),
+        )?;
+
+        let breakdowns = match matches.value_of(BREAKDOWN) {
+            Some(breakdowns) => parse_breakdowns(matches, breakdowns)?,
+            None => Vec::new(),
+        };
+
+        let mut layer_callbacks = match matches.value_of(LAYER) {
+            Some(layers) => named_layer_callbacks(layers).context("Cannot parse layer(s)")?,
+            None => Vec::new(),
         };
+        if let Some(features) = matches.value_of(FEATURE) {
+            layer_callbacks.extend(named_feature_callbacks(features));
+        }
+        if let Some(misc) = matches.value_of(MISC) {
+            layer_callbacks.extend(named_misc_callbacks(misc));
+        }
+        let layer_callbacks =
+            normalize_callbacks(layer_callbacks, Self::value_normalizer(matches)?);
 
         Ok(AccuracyApp {
+            align: matches.is_present(ALIGN),
+            annotate: matches.value_of(ANNOTATE).map(ToOwned::to_owned),
+            attachment_scores: matches.is_present(ATTACHMENT_SCORES),
+            breakdowns,
+            compression: output_compression(matches)?,
+            confusion_matrix,
             default,
-            evaluation,
-            gold_treebank,
-            predicted_treebank,
+            enhanced: matches.is_present(ENHANCED),
+            exact_match: matches.is_present(EXACT_MATCH),
+            folds,
+            layer_callbacks,
+            match_sent_id: matches.is_present(Self::MATCH_SENT_ID),
+            output_format,
+            per_relation: matches.is_present(PER_RELATION),
+            punctuation,
+            sentence_scores: matches.value_of(SENTENCE_SCORES).map(ToOwned::to_owned),
+            top_confusions,
+            training_treebank: matches.value_of(TRAINING_TREEBANK).map(ToOwned::to_owned),
+            ud_metrics: matches.is_present(UD_METRICS),
+            universal_relations: matches.is_present(UNIVERSAL_RELATIONS),
+            vocabulary_layer: VocabularyLayer::from_name(
+                matches.value_of(VOCABULARY).unwrap_or("form"),
+            )?,
         })
     }
 
     fn run(&self) -> Result<()> {
-        let gold_file = File::open(&self.gold_treebank).context(format!(
-            "Cannot open gold standard treebank: {}",
-            self.gold_treebank
-        ))?;
-        let gold_reader = Reader::new(BufReader::new(gold_file));
-
-        let predicted_file = File::open(&self.predicted_treebank).context(format!(
-            "Cannot open predicted treebank: {}",
-            self.predicted_treebank
-        ))?;
-        let predicted_reader = Reader::new(BufReader::new(predicted_file));
-
-        match &self.evaluation {
-            Evaluation::Callbacks(callbacks) => callback_eval(
-                gold_reader,
-                predicted_reader,
-                callbacks,
-                self.default.as_deref(),
+        let mut sentence_scores_writer = match &self.sentence_scores {
+            Some(path) => {
+                let file = File::create(path)
+                    .context(format!("Cannot create sentence scores file: {}", path))?;
+                Some(BufWriter::new(
+                    self.compression
+                        .compress(file, Some(Path::new(path)))
+                        .context(format!("Cannot create sentence scores file: {}", path))?,
+                ))
+            }
+            None => None,
+        };
+        let mut annotate_writer = match &self.annotate {
+            Some(path) => Some(
+                open_word_writer(path, &self.compression)
+                    .context(format!("Cannot create annotated treebank: {}", path))?,
             ),
-            Evaluation::AttachmentScore => dependency_eval(gold_reader, predicted_reader),
-        }
-    }
-}
+            None => None,
+        };
 
-fn callback_eval(
-    reader1: impl IntoIterator<Item = Result<Sentence, Error>>,
-    reader2: impl IntoIterator<Item = Result<Sentence, Error>>,
-    diff_callbacks: &[LayerCallback],
-    default: Option<&str>,
-) -> Result<()> {
-    let mut total = 0;
-    let mut correct = 0;
-
-    for (sent1, sent2) in reader1.into_iter().zip(reader2.into_iter()) {
-        let (sent1, sent2) = (
-            sent1.context("Cannot read sentence from gold treebank")?,
-            sent2.context("Cannot read sentence from predicted treebank")?,
-        );
-
-        ensure!(
-            sent1.len() == sent2.len(),
-            "Different number of tokens: {} {}",
-            sent1.len(),
-            sent2.len()
-        );
-
-        for (token1, token2) in sent1
-            .iter()
-            .filter_map(Node::token)
-            .zip(sent2.iter().filter_map(Node::token))
-        {
-            for layer_callback in diff_callbacks {
-                total += 1;
-
-                match default {
-                    Some(default) => {
-                        if layer_callback(token1).unwrap_or(Cow::Borrowed(default))
-                            == layer_callback(token2).unwrap_or(Cow::Borrowed(default))
-                        {
-                            correct += 1
-                        }
-                    }
-                    None => {
-                        if layer_callback(token1) == layer_callback(token2) {
-                            correct += 1
-                        }
-                    }
+        let vocabulary = match &self.training_treebank {
+            Some(path) => {
+                let reader = open_word_reader(path)
+                    .context(format!("Cannot open training treebank: {}", path))?;
+                Some(Vocabulary::from_treebank(
+                    reader.sentences(),
+                    self.vocabulary_layer,
+                )?)
+            }
+            None => None,
+        };
+
+        let multiple_folds = self.folds.len() > 1;
+        let mut fold_records = Vec::new();
+        let mut stdout = stdout();
+
+        let mut evaluators = self.evaluators(true, vocabulary.as_ref());
+        for (gold_treebank, predicted_treebank) in &self.folds {
+            let gold_reader = open_treebank(gold_treebank, "gold standard")?;
+            let predicted_reader = open_treebank(predicted_treebank, "predicted")?;
+
+            let mut records = Vec::new();
+            // Predicted sentences are only kept when they are annotated.
+            let pairs: Box<dyn Iterator<Item = Result<(SentencePair, Option<Words>)>>> = if self
+                .align
+            {
+                let alignment = align_treebanks(gold_reader.words(), predicted_reader.words())
+                    .context(format!(
+                        "Cannot align treebanks: {} {}",
+                        gold_treebank, predicted_treebank
+                    ))?;
+                records.extend(alignment_records(&alignment));
+                Box::new(alignment.pairs.into_iter().map(|pair| Ok((pair, None))))
+            } else {
+                let mut gold_reader: SentenceIter<Words> = Box::new(gold_reader.words());
+                let mut predicted_reader: SentenceIter<Words> = Box::new(predicted_reader.words());
+                if self.match_sent_id {
+                    let mut matched = match_treebanks(
+                        vec![gold_reader, predicted_reader],
+                        &[gold_treebank, predicted_treebank],
+                    )?;
+                    predicted_reader = matched.pop().unwrap();
+                    gold_reader = matched.pop().unwrap();
+                }
+
+                let annotate = self.annotate.is_some();
+                Box::new(
+                    gold_reader
+                        .zip(predicted_reader)
+                        .map(move |(gold, predicted)| {
+                            let gold = gold.context("Cannot read sentence from gold treebank")?;
+                            let predicted = predicted
+                                .context("Cannot read sentence from predicted treebank")?;
+                            let annotated = if annotate {
+                                Some(predicted.clone())
+                            } else {
+                                None
+                            };
+
+                            Ok((SentencePair::from_words(gold, predicted)?, annotated))
+                        }),
+                )
+            };
+
+            if !multiple_folds {
+                write_records(&mut stdout, self.output_format, &records)?;
+            }
+
+            // Only the scores of folds are needed, since details are
+            // reported over all folds.
+            let mut fold_evaluators = if multiple_folds {
+                self.evaluators(false, vocabulary.as_ref())
+            } else {
+                Vec::new()
+            };
+
+            for (sentence_idx, pair) in pairs.enumerate() {
+                let (pair, annotated) = pair?;
+                for evaluator in evaluators.iter_mut().chain(&mut fold_evaluators) {
+                    evaluator.process(&pair)?;
+                }
+
+                if sentence_scores_writer.is_none() && annotate_writer.is_none() {
+                    continue;
+                }
+
+                let records = self.sentence_records(sentence_idx, &pair)?;
+
+                if let Some(writer) = &mut sentence_scores_writer {
+                    write_records(writer, self.output_format, &records)?;
+                }
+
+                if let (Some(writer), Some(mut words)) = (&mut annotate_writer, annotated) {
+                    add_scores_comment(&mut words.sentence, &records);
+                    writer
+                        .write_words(&words)
+                        .context("Cannot write annotated sentence")?;
                 }
             }
+
+            for evaluator in &fold_evaluators {
+                records.extend(evaluator.scores());
+            }
+            fold_records.push(records);
         }
-    }
 
-    println!(
-        "Accuracy: {:.2} ({}/{})",
-        (100. * correct as f64) / total as f64,
-        correct,
-        total
-    );
+        if let Some(writer) = &mut sentence_scores_writer {
+            writer.flush()?;
+        }
 
-    Ok(())
+        if multiple_folds {
+            self.write_fold_records(&mut stdout, &fold_records)?;
+        } else {
+            for evaluator in &evaluators {
+                write_records(&mut stdout, self.output_format, &evaluator.scores())?;
+            }
+        }
+        for evaluator in &evaluators {
+            evaluator.write_details(&mut stdout, self.output_format)?;
+        }
+
+        Ok(())
+    }
 }
 
-fn dependency_eval(
-    reader1: impl IntoIterator<Item = Result<Sentence, Error>>,
-    reader2: impl IntoIterator<Item = Result<Sentence, Error>>,
-) -> Result<()> {
-    let mut labeled_correct = 0;
-    let mut unlabeled_correct = 0;
-    let mut total = 0;
-
-    let mut nopunct_labeled_correct = 0;
-    let mut nopunct_unlabeled_correct = 0;
-    let mut nopunct_total = 0;
-
-    for (sent1, sent2) in reader1.into_iter().zip(reader2.into_iter()) {
-        let (sent1, sent2) = (
-            sent1.context("Cannot read sentence from gold treebank")?,
-            sent2.context("Cannot read sentence from predicted treebank")?,
-        );
-
-        ensure!(
-            sent1.len() == sent2.len(),
-            "Different number of tokens: {} {}",
-            sent1.len(),
-            sent2.len()
-        );
-
-        for idx in 1..sent1.len() {
-            let form = sent1[idx].token().unwrap().form();
-            let is_punct = form.chars().all(|c| c.is_punctuation());
-
-            total += 1;
-            if !is_punct {
-                nopunct_total += 1;
+impl AccuracyApp {
+    /// Construct the evaluators. Evaluators for sentence and fold scores
+    /// are constructed without `details`.
+    ///
+    /// If a vocabulary is given, evaluators for in-vocabulary and
+    /// out-of-vocabulary tokens are added.
+    fn evaluators<'a>(
+        &'a self,
+        details: bool,
+        vocabulary: Option<&'a Vocabulary>,
+    ) -> Vec<Box<dyn Evaluator + 'a>> {
+        let mut evaluators = self.token_evaluators(details, self.exact_match);
+
+        // Exact match is not defined for a subset of the tokens of a sentence.
+        if let Some(vocabulary) = vocabulary {
+            evaluators.push(Box::new(VocabularyEvaluator::new(
+                vocabulary,
+                self.token_evaluators(false, false),
+                self.token_evaluators(false, false),
+            )));
+        }
+
+        evaluators
+    }
+
+    /// Construct the evaluators of the requested layers and attachment.
+    ///
+    /// Exact-match sentence scores are reported when `exact_match` is true.
+    fn token_evaluators(&self, details: bool, exact_match: bool) -> Vec<Box<dyn Evaluator + '_>> {
+        let mut evaluators: Vec<Box<dyn Evaluator>> = Vec::new();
+        if self.attachment_scores {
+            evaluators.push(Box::new(
+                AttachmentEvaluator::new(details && self.per_relation, self.ud_metrics)
+                    .exact_match(exact_match)
+                    .punctuation(self.punctuation.clone())
+                    .universal_relations(self.universal_relations),
+            ));
+        }
+        if self.enhanced {
+            evaluators.push(Box::new(EnhancedEvaluator::new(
+                details && self.per_relation,
+            )));
+        }
+        if details {
+            for breakdown in &self.breakdowns {
+                evaluators.push(Box::new(BreakdownEvaluator::new(breakdown.clone())));
             }
+        }
+        for (name, callback) in &self.layer_callbacks {
+            let evaluator = LayerEvaluator::new(name.as_str(), callback, self.default.as_deref())
+                .exact_match(exact_match);
+            evaluators.push(Box::new(if details {
+                evaluator
+                    .confusion_matrix(self.confusion_matrix)
+                    .top_confusions(self.top_confusions)
+            } else {
+                evaluator
+            }));
+        }
 
-            let gold_triple = sent1.dep_graph().head(idx).ok_or_else(|| {
-                format_err!(
-                    "Token without head: {} in:\n{}",
-                    idx,
-                    ConlluSentence::borrowed(&sent1)
-                )
-            })?;
-            let predicted_triple = sent2.dep_graph().head(idx).ok_or_else(|| {
-                format_err!(
-                    "Token without head: {} in:\n{}",
-                    idx,
-                    ConlluSentence::borrowed(&sent1)
-                )
-            })?;
+        evaluators
+    }
 
-            if predicted_triple == gold_triple {
-                labeled_correct += 1;
+    /// Write the scores of each fold, followed by their aggregates.
+    ///
+    /// The metrics of fold scores are suffixed with the fold number.
+    /// Settings are written once.
+    fn write_fold_records(
+        &self,
+        writer: &mut impl Write,
+        fold_records: &[Vec<Record>],
+    ) -> Result<()> {
+        let settings = fold_records[0]
+            .iter()
+            .filter(|record| matches!(record, Record::Setting { .. }))
+            .cloned()
+            .collect::<Vec<_>>();
+        write_records(&mut *writer, self.output_format, &settings)?;
+
+        for (fold, records) in fold_records.iter().enumerate() {
+            let records = records
+                .iter()
+                .cloned()
+                .filter_map(|mut record| {
+                    let metric = record.metric_mut()?;
+                    *metric = format!("{}:fold{}", metric, fold + 1);
+                    Some(record)
+                })
+                .collect::<Vec<_>>();
+            write_records(&mut *writer, self.output_format, &records)?;
+        }
 
-                if !is_punct {
-                    nopunct_labeled_correct += 1;
-                }
-            }
+        if self.output_format == OutputFormat::Text {
+            writeln!(writer)?;
+            writeln!(writer, "Metric\tMicro\tMacro\tStd\tFolds")?;
+        }
+        write_records(writer, self.output_format, &aggregate_records(fold_records))?;
 
-            if predicted_triple.head() == gold_triple.head() {
-                unlabeled_correct += 1;
+        Ok(())
+    }
 
-                if !is_punct {
-                    nopunct_unlabeled_correct += 1;
-                }
+    /// Get the scores of a single sentence.
+    fn sentence_records(&self, sentence_idx: usize, pair: &SentencePair) -> Result<Vec<Record>> {
+        let sentence = sentence_id(&pair.gold)
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(|| (sentence_idx + 1).to_string());
+        let length = pair.gold.len() - 1;
+
+        let mut records = Vec::new();
+        for mut evaluator in self.evaluators(false, None) {
+            evaluator.process(pair)?;
+            for record in evaluator.scores() {
+                let (metric, score) = match &record {
+                    Record::Accuracy { metric, score, .. } => (metric, *score),
+                    Record::F1 { metric, f1, .. } => (metric, *f1),
+                    _ => continue,
+                };
+                let counts = F1Counts::from_record(&record).unwrap();
+
+                records.push(Record::sentence(
+                    sentence.as_str(),
+                    length,
+                    metric.as_str(),
+                    score,
+                    counts.correct,
+                    counts.gold,
+                ));
             }
         }
+
+        Ok(records)
     }
+}
 
-    print_dep_result("LAS", labeled_correct, total);
-    print_dep_result("LASnp", nopunct_labeled_correct, nopunct_total);
-    print_dep_result("UAS", unlabeled_correct, total);
-    print_dep_result("UASnp", nopunct_unlabeled_correct, nopunct_total);
+/// Get the gold and predicted treebanks of folds.
+///
+/// If the treebanks are glob patterns, the matching gold and predicted
+/// treebanks are paired in sorted order.
+fn expand_fold(gold: &str, predicted: &str) -> Result<Vec<(String, String)>> {
+    let gold_treebanks = expand_pattern(gold)?;
+    let predicted_treebanks = expand_pattern(predicted)?;
+
+    ensure!(
+        gold_treebanks.len() == predicted_treebanks.len(),
+        "Different number of gold standard ({}) and predicted ({}) treebanks: {} {}",
+        gold_treebanks.len(),
+        predicted_treebanks.len(),
+        gold,
+        predicted
+    );
 
-    Ok(())
+    Ok(gold_treebanks
+        .into_iter()
+        .zip(predicted_treebanks)
+        .collect())
 }
 
-fn feature_callback(feature: impl Into<String>) -> LayerCallback {
-    let feature = feature.into();
+fn expand_pattern(pattern: &str) -> Result<Vec<String>> {
+    if !pattern.contains(&['*', '?', '['][..]) {
+        return Ok(vec![pattern.to_owned()]);
+    }
+
+    let mut paths = glob::glob(pattern)
+        .context(format!("Cannot parse glob pattern: {}", pattern))?
+        .map(|path| Ok(path?.to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!paths.is_empty(), "No treebanks match: {}", pattern);
+    paths.sort();
 
-    Box::new(move |token| {
-        token
-            .features()
-            .get(&feature)
-            .map(|s| Cow::Borrowed(s.as_str()))
-    })
+    Ok(paths)
 }
 
-fn misc_callback(feature: impl Into<String>) -> LayerCallback {
-    let feature = feature.into();
-
-    Box::new(move |token| {
-        token
-            .misc()
-            .get(&feature)
-            .map(Option::as_ref)
-            .unwrap_or(None)
-            .map(|s| Cow::Borrowed(s.as_str()))
-    })
+fn open_treebank(path: &str, description: &str) -> Result<WordReader<Box<dyn BufRead>>> {
+    open_word_reader(&path).context(format!("Cannot open {} treebank: {}", description, path))
 }
 
-fn print_dep_result(desc: &str, correct: usize, total: usize) {
-    println!(
-        "{}\t{:.2}\t{}\t{}",
-        desc,
-        (100. * correct as f64) / total as f64,
-        correct,
-        total
-    );
+fn parse_breakdowns(matches: &ArgMatches, breakdowns: &str) -> Result<Vec<Breakdown>> {
+    let edges = |arg| {
+        let edges = matches.value_of(arg).unwrap();
+        parse_edges(edges).context(format!("Cannot parse bucket edges: {}", edges))
+    };
+
+    breakdowns
+        .split(',')
+        .map(|breakdown| {
+            Ok(match breakdown {
+                "arc-length" => Breakdown::ArcLength(edges(ARC_LENGTH_EDGES)?),
+                "direction" => Breakdown::Direction,
+                "root-distance" => Breakdown::RootDistance(edges(ROOT_DISTANCE_EDGES)?),
+                "sentence-length" => Breakdown::SentenceLength(edges(SENTENCE_LENGTH_EDGES)?),
+                _ => bail!("Unknown breakdown: {}", breakdown),
+            })
+        })
+        .collect()
 }
 
-fn process_layer_callbacks(layers: &str) -> Result<Vec<LayerCallback>> {
-    let mut callbacks = Vec::new();
-    for layer_str in layers.split(',') {
-        match layer_callback(layer_str) {
-            Some(c) => callbacks.push(c),
-            None => {
-                bail!("Unknown layer: {}", layer_str);
-            }
-        }
-    }
+/// Add a comment with the sentence scores, replacing existing scores.
+fn add_scores_comment(sentence: &mut Sentence, records: &[Record]) {
+    let scores = records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Sentence {
+                metric,
+                score,
+                correct,
+                total,
+                ..
+            } => Some(format!("{} {:.2} ({}/{})", metric, score, correct, total)),
+            _ => None,
+        })
+        .join(", ");
+
+    let comments = sentence.comments_mut();
+    comments
+        .retain(|comment| !matches!(comment, Comment::AttrVal { attr, .. } if attr == "scores"));
+    comments.push(Comment::AttrVal {
+        attr: "scores".to_owned(),
+        val: scores,
+    });
+}
 
-    Ok(callbacks)
+/// Get tokenization, sentence segmentation and word scores.
+fn alignment_records(alignment: &Alignment) -> Vec<Record> {
+    let tokens = F1Counts {
+        gold: alignment.gold_tokens,
+        predicted: alignment.predicted_tokens,
+        correct: alignment.aligned_tokens,
+    };
+    let words = F1Counts {
+        gold: alignment.gold_words,
+        predicted: alignment.predicted_words,
+        correct: alignment.aligned_words,
+    };
+    let sentences = F1Counts {
+        gold: alignment.gold_sentences,
+        predicted: alignment.predicted_sentences,
+        correct: alignment.aligned_sentences,
+    };
+
+    vec![
+        tokens.record("Tokens", None),
+        sentences.record("Sentences", None),
+        words.record("Words", None),
+    ]
 }
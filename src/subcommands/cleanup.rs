@@ -2,30 +2,18 @@ use std::io::BufWriter;
 
 use anyhow::{Context, Result};
 use clap::{App, Arg, ArgMatches};
-use conllu::io::{Reader, WriteSentence, Writer};
+use conllu::io::{WriteSentence, Writer};
 use stdinout::{Input, Output};
 use udgraph::graph::{Node, Sentence};
 
-use crate::traits::{ConlluApp, ConlluPipelineApp};
-use crate::unicode::{simplify_unicode, Normalization};
-
-fn normalization_from<S>(value: S) -> Option<Normalization>
-where
-    S: AsRef<str>,
-{
-    match value.as_ref() {
-        "none" => Some(Normalization::None),
-        "nfd" => Some(Normalization::NFD),
-        "nfkd" => Some(Normalization::NFKD),
-        "nfc" => Some(Normalization::NFC),
-        "nfkc" => Some(Normalization::NFKC),
-        _ => None,
-    }
-}
+use crate::io::{input_reader, output_write, OutputCompression};
+use crate::traits::{output_compression, ConlluApp, ConlluPipelineApp};
+use crate::unicode::{normalization_from, simplify_unicode, Normalization};
 
 const NORMALIZATION: &str = "NORMALIZATION";
 
 pub struct CleanupApp {
+    compression: OutputCompression,
     input: Input,
     output: Output,
     normalization: Normalization,
@@ -50,6 +38,7 @@ impl ConlluApp for CleanupApp {
     fn parse(matches: &ArgMatches) -> Result<Self> {
         let input = Input::from(matches.value_of(Self::INPUT));
         let output = Output::from(matches.value_of(Self::OUTPUT));
+        let compression = output_compression(matches)?;
         let normalization = matches
             .value_of(NORMALIZATION)
             .map(|n| normalization_from(n).context(format!("Unknown normalization: {}", n)))
@@ -57,6 +46,7 @@ impl ConlluApp for CleanupApp {
             .unwrap_or(Normalization::None);
 
         Ok(CleanupApp {
+            compression,
             input,
             output,
             normalization,
@@ -64,13 +54,9 @@ impl ConlluApp for CleanupApp {
     }
 
     fn run(&self) -> Result<()> {
-        let reader = Reader::new(
-            self.input
-                .buf_read()
-                .context("Cannot open input treebank")?,
-        );
+        let reader = input_reader(&self.input).context("Cannot open input treebank")?;
         let mut writer = Writer::new(BufWriter::new(
-            self.output.write().context("Cannot open output treebank")?,
+            output_write(&self.output, &self.compression).context("Cannot open output treebank")?,
         ));
 
         for sentence in reader {
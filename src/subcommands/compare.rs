@@ -1,87 +1,189 @@
 use std::borrow::Cow;
-use std::collections::BTreeSet;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::io::stdout;
 
 use anyhow::{bail, ensure, Context, Result};
 use clap::{App, Arg, ArgMatches};
 use colored::Colorize;
-use conllu::io::Reader;
+use itertools::Itertools;
 use udgraph::graph::Sentence;
-use udgraph::token::Tokens;
 
-use crate::layer::{layer_callback, LayerCallback};
-use crate::traits::ConlluApp;
+use crate::align::{match_treebanks, sentence_id, SentenceIter};
+use crate::arcs::{ascii_arcs, svg_arcs};
+use crate::html::{escape, write_page};
+use crate::io::open_reader;
+use crate::layer::{named_layer_callbacks, LayerCallback, ROOT_FORM};
+use crate::normalize::{normalize_callbacks, ValueNormalizer};
+use crate::traits::{ConlluApp, ConlluMatchingApp, ConlluNormalizingApp};
 
+const ARCS: &str = "ARCS";
 const FORCE_COLOR: &str = "FORCE_COLOR";
 const LAYER: &str = "LAYER";
+const MAJORITY: &str = "MAJORITY";
+const OUTPUT_FORMAT: &str = "OUTPUT_FORMAT";
 const SHOW: &str = "SHOW";
-const TREEBANK_1: &str = "TREEBANK_1";
-const TREEBANK_2: &str = "TREEBANK_2";
+const SUMMARY: &str = "SUMMARY";
+const TOP_DIFFERENCES: &str = "TOP_DIFFERENCES";
+const TREEBANKS: &str = "TREEBANKS";
+const TREE: &str = "TREE";
+
+/// How differing sentences are shown.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DiffView {
+    /// Show the values of the compared layers for all tokens.
+    Layers,
+
+    /// Show the heads and relations of differing tokens.
+    Tree,
+}
+
+/// Output format of the comparison.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DiffFormat {
+    Html,
+    Text,
+}
 
 pub struct CompareApp {
+    arcs: bool,
+    diff_view: DiffView,
     force_color: bool,
-    layer_callbacks: Vec<LayerCallback>,
-    show_callbacks: Vec<LayerCallback>,
-    treebank_1: String,
-    treebank_2: String,
+    layer_callbacks: Vec<(String, LayerCallback)>,
+    majority: bool,
+    match_sent_id: bool,
+    output_format: DiffFormat,
+    show_callbacks: Vec<(String, LayerCallback)>,
+    summary: bool,
+    top_differences: usize,
+    treebanks: Vec<String>,
 }
 
+impl ConlluMatchingApp for CompareApp {}
+
+impl ConlluNormalizingApp for CompareApp {}
+
 impl ConlluApp for CompareApp {
     fn app() -> App<'static, 'static> {
-        App::new("compare")
-            .about("Compare two treebanks on specific layers")
-            .arg(
-                Arg::with_name(TREEBANK_1)
-                    .help("First treebank")
-                    .required(true),
-            )
-            .arg(
-                Arg::with_name(TREEBANK_2)
-                    .help("Second treebank")
-                    .required(true),
-            )
-            .arg(
-                Arg::with_name(FORCE_COLOR)
-                    .short("c")
-                    .long("force-color")
-                    .help("Force colored output"),
-            )
-            .arg(
-                Arg::with_name(LAYER)
-                    .short("l")
-                    .long("layer")
-                    .takes_value(true)
-                    .default_value("upos")
-                    .help("Compare a layer"),
-            )
-            .arg(
-                Arg::with_name(SHOW)
-                    .short("s")
-                    .long("show")
-                    .takes_value(true)
-                    .default_value("form")
-                    .help("Compare a layer"),
-            )
+        Self::matching_args(Self::normalizing_args(
+            App::new("compare")
+                .about("Compare treebanks on specific layers")
+                .arg(
+                    Arg::with_name(TREEBANKS)
+                        .help("Treebanks to compare (the first is the reference)")
+                        .min_values(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(ARCS)
+                        .long("arcs")
+                        .help("Show both trees as arc diagrams"),
+                )
+                .arg(
+                    Arg::with_name(FORCE_COLOR)
+                        .short("c")
+                        .long("force-color")
+                        .help("Force colored output"),
+                )
+                .arg(
+                    Arg::with_name(LAYER)
+                        .short("l")
+                        .long("layer")
+                        .takes_value(true)
+                        .default_value("upos")
+                        .help("Compare layers (comma-separated layer expressions)"),
+                )
+                .arg(
+                    Arg::with_name(MAJORITY)
+                        .short("m")
+                        .long("majority")
+                        .help("Only show tokens where most treebanks differ from the first"),
+                )
+                .arg(
+                    Arg::with_name(OUTPUT_FORMAT)
+                        .short("o")
+                        .long("output-format")
+                        .alias("format")
+                        .value_name("FORMAT")
+                        .possible_values(&["html", "text"])
+                        .default_value("text")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name(SHOW)
+                        .short("s")
+                        .long("show")
+                        .takes_value(true)
+                        .default_value("form")
+                        .help("Show layers (comma-separated layer expressions)"),
+                )
+                .arg(
+                    Arg::with_name(SUMMARY)
+                        .long("summary")
+                        .help("Only show summary statistics of the differences"),
+                )
+                .arg(
+                    Arg::with_name(TOP_DIFFERENCES)
+                        .long("top-differences")
+                        .value_name("N")
+                        .default_value("10")
+                        .help("Show the N most frequent differences per layer in the summary"),
+                )
+                .arg(
+                    Arg::with_name(TREE)
+                        .short("t")
+                        .long("tree")
+                        .help("Compare heads and relations instead of the layers given by -l"),
+                ),
+        ))
     }
 
     fn parse(matches: &ArgMatches) -> Result<Self> {
-        let treebank_1 = matches.value_of(TREEBANK_1).unwrap().to_owned();
-        let treebank_2 = matches.value_of(TREEBANK_2).unwrap().to_owned();
+        let treebanks = matches
+            .values_of(TREEBANKS)
+            .unwrap()
+            .map(ToOwned::to_owned)
+            .collect();
 
         let force_color = matches.is_present(FORCE_COLOR);
 
-        let layer_callbacks = process_layer_callbacks(matches.value_of(LAYER).unwrap())
+        let (diff_view, layers) = if matches.is_present(TREE) {
+            (DiffView::Tree, "head,deprel")
+        } else {
+            (DiffView::Layers, matches.value_of(LAYER).unwrap())
+        };
+
+        let layer_callbacks = process_layer_callbacks(layers, Self::value_normalizer(matches)?)
             .context("Cannot parse layer(s) to compare")?;
-        let show_callbacks = process_layer_callbacks(matches.value_of(SHOW).unwrap())
-            .context("Cannot parse layer(s) to show")?;
+        let show_callbacks =
+            process_layer_callbacks(matches.value_of(SHOW).unwrap(), ValueNormalizer::default())
+                .context("Cannot parse layer(s) to show")?;
+
+        let output_format = match matches.value_of(OUTPUT_FORMAT).unwrap() {
+            "html" => DiffFormat::Html,
+            "text" => DiffFormat::Text,
+            format => bail!("Unknown output format: {}", format),
+        };
+
+        let top_differences = matches.value_of(TOP_DIFFERENCES).unwrap();
+        let top_differences = top_differences.parse().context(format!(
+            "Cannot parse number of differences: {}",
+            top_differences
+        ))?;
 
         Ok(CompareApp {
+            arcs: matches.is_present(ARCS),
+            diff_view,
             force_color,
             layer_callbacks,
+            majority: matches.is_present(MAJORITY),
+            match_sent_id: matches.is_present(Self::MATCH_SENT_ID),
+            output_format,
             show_callbacks,
-            treebank_1,
-            treebank_2,
+            summary: matches.is_present(SUMMARY),
+            top_differences,
+            treebanks,
         })
     }
 
@@ -90,117 +192,605 @@ impl ConlluApp for CompareApp {
             colored::control::set_override(true);
         }
 
-        let reader1 = Reader::new(BufReader::new(
-            File::open(&self.treebank_1)
-                .context(format!("Cannot open first treebank: {}", self.treebank_1))?,
-        ));
+        let mut readers = self
+            .treebanks
+            .iter()
+            .map(|treebank| {
+                open_reader(treebank)
+                    .map(|reader| Box::new(reader.into_iter()) as SentenceIter)
+                    .context(format!("Cannot open treebank: {}", treebank))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if self.match_sent_id {
+            let names = self
+                .treebanks
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            readers = match_treebanks(readers, &names)?;
+        }
 
-        let reader2 = Reader::new(BufReader::new(
-            File::open(&self.treebank_2)
-                .context(format!("Cannot open first treebank: {}", self.treebank_2))?,
-        ));
+        let mut diff_writer: Box<dyn DiffWriter> = match self.output_format {
+            DiffFormat::Html => Box::new(HtmlDiffWriter::new(
+                self.arcs,
+                self.header(),
+                self.top_differences,
+                self.treebanks.clone(),
+            )),
+            DiffFormat::Text => Box::new(TextDiffWriter {
+                arcs: self.arcs,
+                summary: self.summary,
+                top_differences: self.top_differences,
+            }),
+        };
 
-        compare_sentences(
-            reader1,
-            reader2,
-            &self.layer_callbacks,
-            &self.show_callbacks,
-        )
-        .context("Cannot compare sentences")
+        self.compare_sentences(readers, &mut *diff_writer)
+            .context("Cannot compare sentences")
     }
 }
 
-fn process_layer_callbacks(layers: &str) -> Result<Vec<LayerCallback>> {
-    let mut callbacks = Vec::new();
-    for layer_str in layers.split(',') {
-        match layer_callback(layer_str) {
-            Some(c) => callbacks.push(c),
-            None => {
-                bail!("Unknown layer: {}", layer_str);
+impl CompareApp {
+    fn compare_sentences(
+        &self,
+        mut readers: Vec<SentenceIter>,
+        diff_writer: &mut dyn DiffWriter,
+    ) -> Result<()> {
+        let mut summary = CompareSummary::new(
+            self.layer_callbacks
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+        );
+
+        for sentence_idx in 0.. {
+            // Stop when one of the treebanks is exhausted.
+            let sentences = match readers
+                .iter_mut()
+                .map(Iterator::next)
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(sentences) => sentences,
+                None => break,
+            };
+
+            let sentences = sentences
+                .into_iter()
+                .zip(&self.treebanks)
+                .map(|(sentence, treebank)| {
+                    sentence.context(format!("Cannot read sentence from treebank: {}", treebank))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let diff = self.diff_indices(&sentences)?;
+
+            summary.sentences += 1;
+            summary.tokens += sentences[0].len() - 1;
+
+            if diff.is_empty() {
+                continue;
+            }
+
+            summary.differing_sentences += 1;
+            summary.differing_tokens += diff.len();
+
+            let rows = self.diff_rows(&sentences, &diff);
+            summary.add_rows(&rows);
+
+            if !self.summary {
+                diff_writer.write_sentence(&SentenceDiff {
+                    sentence_idx,
+                    sentences: &sentences,
+                    indices: &diff,
+                    rows,
+                })?;
             }
         }
+
+        diff_writer.finish(&summary)
     }
 
-    Ok(callbacks)
-}
+    /// Get the column names of the rows of a sentence difference.
+    fn header(&self) -> Vec<String> {
+        let mut header = vec!["#".to_owned()];
+        header.extend(self.show_callbacks.iter().map(|(name, _)| name.clone()));
+        for (name, _) in &self.layer_callbacks {
+            for treebank_idx in 0..self.treebanks.len() {
+                header.push(format!("{} ({})", name, treebank_idx + 1));
+            }
+        }
+        header
+    }
 
-fn compare_sentences(
-    reader1: Reader<impl BufRead>,
-    reader2: Reader<impl BufRead>,
-    diff_callbacks: &[LayerCallback],
-    show_callbacks: &[LayerCallback],
-) -> Result<()> {
-    for (sent1, sent2) in reader1.into_iter().zip(reader2.into_iter()) {
-        let (sent1, sent2) = (
-            sent1.context("Cannot read sentence from first treebank")?,
-            sent2.context("Cannot read sentence from second treebank")?,
+    /// Check whether the values of a layer differ.
+    ///
+    /// When only majority differences are considered, the values differ
+    /// when more than half of the treebanks differ from the first.
+    fn values_differ<T: PartialEq>(&self, values: &[T]) -> bool {
+        let (first, rest) = values.split_first().expect("No values to compare");
+        let n_differing = rest.iter().filter(|&value| value != first).count();
+
+        if self.majority {
+            n_differing * 2 > rest.len()
+        } else {
+            n_differing > 0
+        }
+    }
+
+    /// Get the indices of the tokens that differ.
+    fn diff_indices(&self, sentences: &[Sentence]) -> Result<BTreeSet<usize>> {
+        let len = sentences[0].len();
+        ensure!(
+            sentences.iter().all(|sentence| sentence.len() == len),
+            "Different number of tokens: {}",
+            sentences
+                .iter()
+                .map(|sentence| sentence.len() - 1)
+                .join(" ")
         );
 
-        let diff = diff_indices(&sent1, &sent2, diff_callbacks)?;
+        let mut indices = BTreeSet::new();
 
-        if !diff.is_empty() {
-            print_diff(&sent1, &sent2, diff_callbacks, show_callbacks);
-            println!();
+        for idx in 1..len {
+            for (_, layer_callback) in &self.layer_callbacks {
+                let values = sentences
+                    .iter()
+                    .map(|sentence| layer_callback(sentence, idx))
+                    .collect::<Vec<_>>();
+                if self.values_differ(&values) {
+                    indices.insert(idx);
+                    break;
+                }
+            }
         }
+
+        Ok(indices)
+    }
+
+    /// Get the rows to show for differing sentences.
+    fn diff_rows(&self, sentences: &[Sentence], diff: &BTreeSet<usize>) -> Vec<DiffRow> {
+        let indices: Vec<usize> = match self.diff_view {
+            DiffView::Layers => (1..sentences[0].len()).collect(),
+            DiffView::Tree => diff.iter().copied().collect(),
+        };
+
+        indices
+            .into_iter()
+            .map(|idx| {
+                let show = self
+                    .show_callbacks
+                    .iter()
+                    .map(|(_, callback)| layer_value(callback, &sentences[0], idx))
+                    .collect();
+
+                let values: Vec<Vec<String>> = match self.diff_view {
+                    DiffView::Layers => self
+                        .layer_callbacks
+                        .iter()
+                        .map(|(_, callback)| {
+                            sentences
+                                .iter()
+                                .map(|sentence| layer_value(callback, sentence, idx))
+                                .collect()
+                        })
+                        .collect(),
+                    DiffView::Tree => {
+                        let (heads, relations) = sentences
+                            .iter()
+                            .map(|sentence| attachment(sentence, idx))
+                            .unzip();
+                        vec![heads, relations]
+                    }
+                };
+
+                let differs = values
+                    .iter()
+                    .map(|values| self.values_differ(values))
+                    .collect();
+
+                DiffRow {
+                    idx,
+                    show,
+                    values,
+                    differs,
+                }
+            })
+            .collect()
     }
+}
+
+fn process_layer_callbacks(
+    layers: &str,
+    normalizer: ValueNormalizer,
+) -> Result<Vec<(String, LayerCallback)>> {
+    Ok(normalize_callbacks(
+        named_layer_callbacks(layers)?,
+        normalizer,
+    ))
+}
+
+/// Get the value of a layer, using `_` for missing values.
+fn layer_value(callback: &LayerCallback, sentence: &Sentence, idx: usize) -> String {
+    callback(sentence, idx)
+        .unwrap_or(Cow::Borrowed("_"))
+        .into_owned()
+}
 
-    Result::Ok(())
+/// Summary statistics of a comparison.
+struct CompareSummary {
+    sentences: usize,
+    differing_sentences: usize,
+    tokens: usize,
+    differing_tokens: usize,
+    layers: Vec<LayerSummary>,
 }
 
-fn print_diff(
-    sentence1: &Sentence,
-    sentence2: &Sentence,
-    diff_callbacks: &[LayerCallback],
-    show_callbacks: &[LayerCallback],
-) {
-    for (idx, (token1, token2)) in sentence1.tokens().zip(sentence2.tokens()).enumerate() {
-        let mut columns = Vec::new();
+impl CompareSummary {
+    fn new(layers: Vec<String>) -> Self {
+        CompareSummary {
+            sentences: 0,
+            differing_sentences: 0,
+            tokens: 0,
+            differing_tokens: 0,
+            layers: layers
+                .into_iter()
+                .map(|name| LayerSummary {
+                    name,
+                    differing_tokens: 0,
+                    differences: HashMap::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Add the differences of the rows of a sentence difference.
+    ///
+    /// The differences are counted as pairs of the value in the first
+    /// treebank and a differing value in another treebank.
+    fn add_rows(&mut self, rows: &[DiffRow]) {
+        for row in rows {
+            for ((layer, values), &differs) in
+                self.layers.iter_mut().zip(&row.values).zip(&row.differs)
+            {
+                if !differs {
+                    continue;
+                }
+
+                layer.differing_tokens += 1;
 
-        for callback in show_callbacks {
-            columns.push(callback(token1).unwrap_or(Cow::Borrowed("_")).into_owned());
+                let (first, rest) = values.split_first().unwrap();
+                for value in rest.iter().filter(|&value| value != first) {
+                    *layer
+                        .differences
+                        .entry((first.clone(), value.clone()))
+                        .or_default() += 1;
+                }
+            }
         }
+    }
+}
+
+/// Summary statistics of the differences in a layer.
+struct LayerSummary {
+    name: String,
+    differing_tokens: usize,
+    differences: HashMap<(String, String), usize>,
+}
 
-        for callback in diff_callbacks {
-            let col1 = callback(token1).unwrap_or(Cow::Borrowed("_"));
-            let col2 = callback(token2).unwrap_or(Cow::Borrowed("_"));
+impl LayerSummary {
+    /// Get the `n` most frequent differences.
+    fn top_differences(&self, n: usize) -> Vec<(&str, &str, usize)> {
+        self.differences
+            .iter()
+            .map(|((value1, value2), &count)| (value1.as_str(), value2.as_str(), count))
+            .sorted_by_key(|&(value1, value2, count)| (Reverse(count), value1, value2))
+            .take(n)
+            .collect()
+    }
+}
 
-            if col1 != col2 {
-                columns.push(format!("{}", col1.red()));
-                columns.push(format!("{}", col2.red()));
-            } else {
-                columns.push(col1.into_owned());
-                columns.push(col2.into_owned());
+/// A token of a sentence difference.
+struct DiffRow {
+    idx: usize,
+
+    /// Values of the layers that are shown.
+    show: Vec<String>,
+
+    /// Values of the compared layers, per layer the values in each
+    /// treebank.
+    values: Vec<Vec<String>>,
+
+    /// Whether the values of a layer differ.
+    differs: Vec<bool>,
+}
+
+/// Sentences that differ.
+struct SentenceDiff<'a> {
+    sentence_idx: usize,
+    sentences: &'a [Sentence],
+
+    /// Indices of the tokens that differ.
+    indices: &'a BTreeSet<usize>,
+
+    rows: Vec<DiffRow>,
+}
+
+/// Writer of sentence differences.
+trait DiffWriter {
+    /// Write sentences that differ.
+    fn write_sentence(&mut self, diff: &SentenceDiff) -> Result<()>;
+
+    /// Finish writing after all sentences are compared.
+    fn finish(&mut self, summary: &CompareSummary) -> Result<()>;
+}
+
+/// Writer of sentence differences as colored text.
+struct TextDiffWriter {
+    arcs: bool,
+    summary: bool,
+    top_differences: usize,
+}
+
+impl DiffWriter for TextDiffWriter {
+    fn write_sentence(&mut self, diff: &SentenceDiff) -> Result<()> {
+        for row in &diff.rows {
+            let mut columns = row.show.clone();
+
+            for (values, &differs) in row.values.iter().zip(&row.differs) {
+                for (treebank_idx, value) in values.iter().enumerate() {
+                    if differs && (treebank_idx == 0 || *value != values[0]) {
+                        columns.push(format!("{}", value.red()));
+                    } else {
+                        columns.push(value.clone());
+                    }
+                }
             }
+
+            println!("{}\t{}", row.idx, columns.join("\t"));
+        }
+
+        if self.arcs {
+            println!();
+            print_arcs(diff.sentences, diff.indices);
         }
 
-        println!("{}\t{}", idx + 1, columns.join("\t"));
+        println!();
+
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &CompareSummary) -> Result<()> {
+        if !self.summary {
+            return Ok(());
+        }
+
+        println!("Sentences\t{}", summary.sentences);
+        println!("Differing sentences\t{}", summary.differing_sentences);
+        println!("Tokens\t{}", summary.tokens);
+        println!("Differing tokens\t{}", summary.differing_tokens);
+
+        println!("\nLayer\tDiffering tokens");
+        for layer in &summary.layers {
+            println!("{}\t{}", layer.name, layer.differing_tokens);
+        }
+
+        for layer in &summary.layers {
+            println!("\nDifferences: {}", layer.name);
+            for (value1, value2, count) in layer.top_differences(self.top_differences) {
+                println!("{}→{}\t{}", value1, value2, count);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writer of sentence differences as a self-contained HTML page.
+///
+/// The page is written when all sentences are compared, so that it can
+/// start with the summary counts.
+struct HtmlDiffWriter {
+    arcs: bool,
+    body: String,
+    header: Vec<String>,
+    top_differences: usize,
+    treebanks: Vec<String>,
+}
+
+impl HtmlDiffWriter {
+    fn new(
+        arcs: bool,
+        header: Vec<String>,
+        top_differences: usize,
+        treebanks: Vec<String>,
+    ) -> Self {
+        HtmlDiffWriter {
+            arcs,
+            body: String::new(),
+            header,
+            top_differences,
+            treebanks,
+        }
     }
 }
 
-fn diff_indices(
-    sentence1: &Sentence,
-    sentence2: &Sentence,
-    diff_callbacks: &[LayerCallback],
-) -> Result<BTreeSet<usize>> {
-    ensure!(
-        sentence1.len() == sentence2.len(),
-        "Different number of tokens: {} {}",
-        sentence1.len(),
-        sentence2.len()
-    );
+impl DiffWriter for HtmlDiffWriter {
+    fn write_sentence(&mut self, diff: &SentenceDiff) -> Result<()> {
+        let body = &mut self.body;
 
-    let mut indices = BTreeSet::new();
+        match sentence_id(&diff.sentences[0]) {
+            Some(id) => writeln!(
+                body,
+                "<h2>Sentence {}: {}</h2>",
+                diff.sentence_idx + 1,
+                escape(id)
+            )?,
+            None => writeln!(body, "<h2>Sentence {}</h2>", diff.sentence_idx + 1)?,
+        }
 
-    'tokenloop: for (idx, (token1, token2)) in
-        sentence1.tokens().zip(sentence2.tokens()).enumerate()
-    {
-        for layer_callback in diff_callbacks {
-            if layer_callback(token1) != layer_callback(token2) {
-                indices.insert(idx);
-                continue 'tokenloop;
+        writeln!(body, "<table>")?;
+        write!(body, "<tr>")?;
+        for name in &self.header {
+            write!(body, "<th>{}</th>", escape(name))?;
+        }
+        writeln!(body, "</tr>")?;
+
+        for row in &diff.rows {
+            write!(body, "<tr><td>{}</td>", row.idx)?;
+            for value in &row.show {
+                write!(body, "<td>{}</td>", escape(value))?;
+            }
+            for (values, &differs) in row.values.iter().zip(&row.differs) {
+                for (treebank_idx, value) in values.iter().enumerate() {
+                    let class = if differs && (treebank_idx == 0 || *value != values[0]) {
+                        " class=\"diff\""
+                    } else {
+                        ""
+                    };
+                    write!(body, "<td{}>{}</td>", class, escape(value))?;
+                }
+            }
+            writeln!(body, "</tr>")?;
+        }
+        writeln!(body, "</table>")?;
+
+        if self.arcs {
+            writeln!(body, "<div class=\"arcs\">")?;
+            for (sentence, treebank) in diff.sentences.iter().zip(&self.treebanks) {
+                writeln!(
+                    body,
+                    "<figure>\n{}<figcaption>{}</figcaption>\n</figure>",
+                    svg_arcs(sentence, |idx| diff.indices.contains(&idx)),
+                    escape(treebank)
+                )?;
+            }
+            writeln!(body, "</div>")?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &CompareSummary) -> Result<()> {
+        let mut page = String::new();
+        writeln!(page, "<table>")?;
+        for (idx, treebank) in self.treebanks.iter().enumerate() {
+            writeln!(
+                page,
+                "<tr><th>Treebank {}</th><td>{}</td></tr>",
+                idx + 1,
+                escape(treebank)
+            )?;
+        }
+        for (name, count) in [
+            ("Sentences", summary.sentences),
+            ("Differing sentences", summary.differing_sentences),
+            ("Tokens", summary.tokens),
+            ("Differing tokens", summary.differing_tokens),
+        ] {
+            writeln!(page, "<tr><th>{}</th><td>{}</td></tr>", name, count)?;
+        }
+        writeln!(page, "</table>")?;
+
+        writeln!(page, "<table>")?;
+        writeln!(page, "<tr><th>Layer</th><th>Differing tokens</th></tr>")?;
+        for layer in &summary.layers {
+            writeln!(
+                page,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape(&layer.name),
+                layer.differing_tokens
+            )?;
+        }
+        writeln!(page, "</table>")?;
+
+        for layer in &summary.layers {
+            writeln!(page, "<h2>Differences: {}</h2>", escape(&layer.name))?;
+            writeln!(page, "<table>")?;
+            writeln!(
+                page,
+                "<tr><th>{0} (1)</th><th>{0} (other)</th><th>Count</th></tr>",
+                escape(&layer.name)
+            )?;
+            for (value1, value2, count) in layer.top_differences(self.top_differences) {
+                writeln!(
+                    page,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape(value1),
+                    escape(value2),
+                    count
+                )?;
             }
+            writeln!(page, "</table>")?;
         }
+
+        page.push_str(&self.body);
+
+        write_page(&mut stdout().lock(), "Treebank comparison", &page)
+            .context("Cannot write HTML report")
+    }
+}
+
+/// Get the head (with its form) and relation of a token.
+fn attachment(sentence: &Sentence, idx: usize) -> (String, String) {
+    match sentence.dep_graph().head(idx) {
+        Some(triple) => {
+            let head_form = sentence[triple.head()]
+                .token()
+                .map(|token| token.form())
+                .unwrap_or(ROOT_FORM);
+            (
+                format!("{} ({})", triple.head(), head_form),
+                triple.relation().unwrap_or("_").to_owned(),
+            )
+        }
+        None => ("_".to_owned(), "_".to_owned()),
+    }
+}
+
+/// Print the arc diagrams of sentences side by side.
+fn print_arcs(sentences: &[Sentence], diff: &BTreeSet<usize>) {
+    let lines = sentences.iter().map(arc_lines).collect::<Vec<_>>();
+    let widths = lines
+        .iter()
+        .map(|lines| {
+            lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    for idx in 0..lines[0].len() {
+        let mut columns = lines.iter().zip(&widths).map(|(lines, &width)| {
+            let line = &lines[idx];
+            let padding = " ".repeat(width - line.chars().count());
+            if diff.contains(&(idx + 1)) {
+                format!("{}{}", line.red(), padding)
+            } else {
+                format!("{}{}", line, padding)
+            }
+        });
+        println!("{}", columns.join("    ").trim_end());
     }
+}
 
-    Result::Ok(indices)
+/// Render the arc diagram of a sentence, one line per token.
+fn arc_lines(sentence: &Sentence) -> Vec<String> {
+    ascii_arcs(sentence)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, arcs)| {
+            let idx = idx + 1;
+            let form = sentence[idx]
+                .token()
+                .map(|token| token.form())
+                .unwrap_or("_");
+            let relation = sentence
+                .dep_graph()
+                .head(idx)
+                .and_then(|triple| triple.relation().map(ToOwned::to_owned))
+                .unwrap_or_else(|| "_".to_owned());
+            format!("{} {} {} ({})", arcs, idx, form, relation)
+        })
+        .collect()
 }
@@ -1,12 +1,12 @@
 use anyhow::{Context, Result};
 use clap::{App, Arg, ArgMatches};
 use conllu::display::ConlluSentence;
-use conllu::io::Reader;
 use itertools::Itertools;
 use petgraph::algo::kosaraju_scc;
 use stdinout::Input;
 use udgraph::graph::Sentence;
 
+use crate::io::input_reader;
 use crate::traits::ConlluApp;
 
 const INPUT: &str = "INPUT";
@@ -29,11 +29,7 @@ impl ConlluApp for CycleApp {
     }
 
     fn run(&self) -> Result<()> {
-        let reader = Reader::new(
-            self.input
-                .buf_read()
-                .context("Cannot open input treebank")?,
-        );
+        let reader = input_reader(&self.input).context("Cannot open input treebank")?;
 
         for sentence in reader {
             let sentence = sentence.context("Cannot parse sentence")?;
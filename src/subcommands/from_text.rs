@@ -7,9 +7,11 @@ use stdinout::{Input, Output};
 use udgraph::graph::Sentence;
 use udgraph::token::TokenBuilder;
 
-use crate::traits::{ConlluApp, ConlluPipelineApp};
+use crate::io::{input_buf_read, output_write, OutputCompression};
+use crate::traits::{output_compression, ConlluApp, ConlluPipelineApp};
 
 pub struct FromTextApp {
+    compression: OutputCompression,
     input: Input,
     output: Output,
 }
@@ -24,15 +26,20 @@ impl ConlluApp for FromTextApp {
     fn parse(matches: &ArgMatches) -> Result<Self> {
         let input = Input::from(matches.value_of(Self::INPUT));
         let output = Output::from(matches.value_of(Self::OUTPUT));
+        let compression = output_compression(matches)?;
 
-        Ok(FromTextApp { input, output })
+        Ok(FromTextApp {
+            compression,
+            input,
+            output,
+        })
     }
 
     fn run(&self) -> Result<()> {
-        let reader = self.input.buf_read().context("Cannot open input corpus")?;
+        let reader = input_buf_read(&self.input).context("Cannot open input corpus")?;
 
         let mut writer = conllu::io::Writer::new(BufWriter::new(
-            self.output.write().context("Cannot open output treebank")?,
+            output_write(&self.output, &self.compression).context("Cannot open output treebank")?,
         ));
 
         for line in reader.lines() {
@@ -5,13 +5,14 @@ use clap::{App, Arg, ArgMatches};
 use conllu::io::{WriteSentence, Writer};
 use stdinout::Output;
 
-use crate::io::open_reader;
-use crate::traits::ConlluApp;
+use crate::io::{open_reader, output_write, OutputCompression};
+use crate::traits::{output_compression, ConlluApp};
 
 static INPUTS: &str = "INPUTS";
 static OUTPUT: &str = "OUTPUT";
 
 pub struct MergeApp {
+    compression: OutputCompression,
     inputs: Vec<String>,
     output: Output,
 }
@@ -41,14 +42,18 @@ impl ConlluApp for MergeApp {
             .map(ToOwned::to_owned)
             .collect();
         let output = Output::from(matches.value_of(OUTPUT));
+        let compression = output_compression(matches)?;
 
-        Ok(MergeApp { inputs, output })
+        Ok(MergeApp {
+            compression,
+            inputs,
+            output,
+        })
     }
 
     fn run(&self) -> Result<()> {
         let mut writer = Writer::new(BufWriter::new(
-            self.output
-                .write()
+            output_write(&self.output, &self.compression)
                 .context("Cannot open output for writing")?,
         ));
 
@@ -19,6 +19,9 @@ pub use merge::MergeApp;
 mod partition;
 pub use partition::PartitionApp;
 
+mod significance;
+pub use significance::SignificanceApp;
+
 mod shuffle;
 pub use shuffle::ShuffleApp;
 
@@ -1,10 +1,10 @@
 use anyhow::{ensure, Context, Result};
 use clap::{App, Arg, ArgMatches};
-use conllu::io::{PartitioningWriter, Reader, WriteSentence};
+use conllu::io::{PartitioningWriter, WriteSentence};
 use stdinout::Input;
 
-use crate::io::open_writer;
-use crate::traits::ConlluApp;
+use crate::io::{input_reader, open_writer, OutputCompression};
+use crate::traits::{output_compression, ConlluApp};
 
 const INPUT: &str = "INPUT";
 const N_PARTS: &str = "N_PARTS";
@@ -12,6 +12,7 @@ const PREFIX: &str = "PREFIX";
 const SUFFIX: &str = "SUFFIX";
 
 pub struct PartitionApp {
+    compression: OutputCompression,
     input: Input,
     n_parts: usize,
     prefix: String,
@@ -52,8 +53,10 @@ impl ConlluApp for PartitionApp {
 
         let prefix = matches.value_of(PREFIX).unwrap().to_owned();
         let suffix = matches.value_of(SUFFIX).unwrap().to_owned();
+        let compression = output_compression(matches)?;
 
         Ok(PartitionApp {
+            compression,
             input,
             n_parts,
             prefix,
@@ -62,16 +65,15 @@ impl ConlluApp for PartitionApp {
     }
 
     fn run(&self) -> Result<()> {
-        let reader = Reader::new(
-            self.input
-                .buf_read()
-                .context("Cannot open input treebank")?,
-        );
+        let reader = input_reader(&self.input).context("Cannot open input treebank")?;
 
         let writers = (0..self.n_parts)
             .map(|part| {
-                open_writer(&format!("{}{}{}", self.prefix, part, self.suffix))
-                    .context(format!("Cannot open writer for partition {}", part))
+                open_writer(
+                    &format!("{}{}{}", self.prefix, part, self.suffix),
+                    &self.compression,
+                )
+                .context(format!("Cannot open writer for partition {}", part))
             })
             .collect::<Result<Vec<_>>>()?;
 
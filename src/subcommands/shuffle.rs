@@ -2,17 +2,19 @@ use std::io::BufWriter;
 
 use anyhow::{Context, Result};
 use clap::{App, Arg, ArgMatches};
-use conllu::io::{Reader, WriteSentence, Writer};
+use conllu::io::{WriteSentence, Writer};
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use stdinout::{Input, Output};
 
-use crate::traits::{ConlluApp, ConlluPipelineApp};
+use crate::io::{input_reader, output_write, OutputCompression};
+use crate::traits::{output_compression, ConlluApp, ConlluPipelineApp};
 
 const SEED: &str = "SEED";
 
 pub struct ShuffleApp {
+    compression: OutputCompression,
     input: Input,
     output: Output,
     seed: [u8; 16],
@@ -36,6 +38,7 @@ impl ConlluApp for ShuffleApp {
     fn parse(matches: &ArgMatches) -> Result<Self> {
         let input = Input::from(matches.value_of(Self::INPUT));
         let output = Output::from(matches.value_of(Self::OUTPUT));
+        let compression = output_compression(matches)?;
 
         let seed = if let Some(seed_str) = matches.value_of(SEED) {
             let mut seed = [0; 16];
@@ -49,6 +52,7 @@ impl ConlluApp for ShuffleApp {
         };
 
         Ok(ShuffleApp {
+            compression,
             input,
             output,
             seed,
@@ -58,9 +62,9 @@ impl ConlluApp for ShuffleApp {
     fn run(&self) -> Result<()> {
         let mut rng = XorShiftRng::from_seed(self.seed);
 
-        let reader = Reader::new(self.input.buf_read().context("Cannot open input corpus")?);
+        let reader = input_reader(&self.input).context("Cannot open input corpus")?;
         let mut writer = Writer::new(BufWriter::new(
-            self.output.write().context("Cannot open output corpus")?,
+            output_write(&self.output, &self.compression).context("Cannot open output corpus")?,
         ));
 
         let mut sents = reader
@@ -0,0 +1,529 @@
+use std::io::{stdout, BufRead};
+
+use anyhow::{ensure, Context, Result};
+use clap::{App, Arg, ArgGroup, ArgMatches};
+use conllu::io::ReadSentence;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use udgraph::graph::Sentence;
+
+use crate::align::{match_treebanks, SentenceIter, SentencePair};
+use crate::eval::{AttachmentEvaluator, Evaluator, F1Counts, LayerEvaluator};
+use crate::io::{open_word_reader, WordReader};
+use crate::layer::{
+    named_feature_callbacks, named_layer_callbacks, named_misc_callbacks, LayerCallback,
+};
+use crate::report::{output_format_from, write_records, OutputFormat, Record};
+use crate::traits::{ConlluApp, ConlluMatchingApp};
+
+const ATTACHMENT_SCORES: &str = "ATTACHMENT_SCORES";
+const CONFIDENCE: &str = "CONFIDENCE";
+const DEFAULT: &str = "DEFAULT";
+const FEATURE: &str = "FEATURE";
+const GOLD_TREEBANK: &str = "GOLD_TREEBANK";
+const LAYER: &str = "LAYER";
+const MISC: &str = "MISC";
+const OUTPUT_FORMAT: &str = "OUTPUT_FORMAT";
+const PREDICTED_TREEBANK_A: &str = "PREDICTED_TREEBANK_A";
+const PREDICTED_TREEBANK_B: &str = "PREDICTED_TREEBANK_B";
+const SAMPLES: &str = "SAMPLES";
+const SEED: &str = "SEED";
+const TEST: &str = "TEST";
+
+/// Significance tests.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Test {
+    /// Paired bootstrap resampling of sentences.
+    Bootstrap,
+
+    /// Approximate randomization, swapping the sentences of the systems.
+    Randomization,
+}
+
+impl Test {
+    fn name(self) -> &'static str {
+        match self {
+            Test::Bootstrap => "bootstrap",
+            Test::Randomization => "randomization",
+        }
+    }
+}
+
+fn test_from<S>(value: S) -> Option<Test>
+where
+    S: AsRef<str>,
+{
+    match value.as_ref() {
+        "bootstrap" => Some(Test::Bootstrap),
+        "randomization" => Some(Test::Randomization),
+        _ => None,
+    }
+}
+
+pub struct SignificanceApp {
+    attachment_scores: bool,
+    confidence: f64,
+    default: Option<String>,
+    gold_treebank: String,
+    layer_callbacks: Vec<(String, LayerCallback)>,
+    match_sent_id: bool,
+    output_format: OutputFormat,
+    predicted_treebank_a: String,
+    predicted_treebank_b: String,
+    samples: usize,
+    seed: [u8; 16],
+    test: Test,
+}
+
+impl ConlluMatchingApp for SignificanceApp {}
+
+impl ConlluApp for SignificanceApp {
+    fn app() -> App<'static, 'static> {
+        Self::matching_args(
+            App::new("significance")
+                .about("Test whether the scores of two systems differ significantly")
+                .arg(
+                    Arg::with_name(GOLD_TREEBANK)
+                        .help("Gold standard treebank")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(PREDICTED_TREEBANK_A)
+                        .help("Treebank predicted by system A")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(PREDICTED_TREEBANK_B)
+                        .help("Treebank predicted by system B")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name(ATTACHMENT_SCORES)
+                        .short("a")
+                        .long("attachment")
+                        .help("Test attachment scores"),
+                )
+                .arg(
+                    Arg::with_name(DEFAULT)
+                        .short("d")
+                        .long("default")
+                        .takes_value(true)
+                        .help("Default value when no value is present"),
+                )
+                .arg(
+                    Arg::with_name(LAYER)
+                        .short("l")
+                        .long("layer")
+                        .takes_value(true)
+                        .help("Test layers (comma-separated layer expressions)"),
+                )
+                .arg(
+                    Arg::with_name(FEATURE)
+                        .short("f")
+                        .long("feature")
+                        .takes_value(true)
+                        .help("Test morphological features (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(MISC)
+                        .short("m")
+                        .long("misc")
+                        .takes_value(true)
+                        .help("Test miscellaneous features (comma-separated)"),
+                )
+                .arg(
+                    Arg::with_name(TEST)
+                        .short("t")
+                        .long("test")
+                        .possible_values(&["bootstrap", "randomization"])
+                        .default_value("bootstrap")
+                        .help("Significance test"),
+                )
+                .arg(
+                    Arg::with_name(SAMPLES)
+                        .short("n")
+                        .long("samples")
+                        .value_name("N")
+                        .default_value("10000")
+                        .help("Number of bootstrap samples or randomizations"),
+                )
+                .arg(
+                    Arg::with_name(CONFIDENCE)
+                        .short("c")
+                        .long("confidence")
+                        .value_name("LEVEL")
+                        .default_value("0.95")
+                        .help("Confidence level of the confidence intervals"),
+                )
+                .arg(
+                    Arg::with_name(SEED)
+                        .short("s")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("Random number generator seed"),
+                )
+                .arg(
+                    Arg::with_name(OUTPUT_FORMAT)
+                        .short("o")
+                        .long("output-format")
+                        .value_name("FORMAT")
+                        .possible_values(&["json", "text", "tsv"])
+                        .default_value("text")
+                        .help("Output format"),
+                )
+                .group(
+                    ArgGroup::with_name("source")
+                        .args(&[ATTACHMENT_SCORES, FEATURE, LAYER, MISC])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+    }
+
+    fn parse(matches: &ArgMatches) -> Result<Self> {
+        let gold_treebank = matches.value_of(GOLD_TREEBANK).unwrap().to_owned();
+        let predicted_treebank_a = matches.value_of(PREDICTED_TREEBANK_A).unwrap().to_owned();
+        let predicted_treebank_b = matches.value_of(PREDICTED_TREEBANK_B).unwrap().to_owned();
+
+        let default = matches.value_of(DEFAULT).map(|s| s.to_owned());
+
+        let output_format = matches.value_of(OUTPUT_FORMAT).unwrap();
+        let output_format = output_format_from(output_format)
+            .context(format!("Unknown output format: {}", output_format))?;
+
+        let test = matches.value_of(TEST).unwrap();
+        let test = test_from(test).context(format!("Unknown significance test: {}", test))?;
+
+        let samples = matches.value_of(SAMPLES).unwrap();
+        let samples: usize = samples.parse().context(format!(
+            "Number of samples could not be parsed as an integer: {}",
+            samples
+        ))?;
+        ensure!(samples != 0, "The number of samples should be positive");
+
+        let confidence = matches.value_of(CONFIDENCE).unwrap();
+        let confidence: f64 = confidence.parse().context(format!(
+            "Confidence level could not be parsed as a number: {}",
+            confidence
+        ))?;
+        ensure!(
+            confidence > 0. && confidence < 1.,
+            "The confidence level should be between 0 and 1"
+        );
+
+        let seed = if let Some(seed_str) = matches.value_of(SEED) {
+            let mut seed = [0; 16];
+            let seed_val: u32 = seed_str
+                .parse()
+                .context(format!("Cannot not parse '{}' as an integer", seed_str))?;
+            seed[..4].copy_from_slice(&seed_val.to_be_bytes());
+            seed
+        } else {
+            rand::thread_rng().gen()
+        };
+
+        let mut layer_callbacks = match matches.value_of(LAYER) {
+            Some(layers) => named_layer_callbacks(layers).context("Cannot parse layer(s)")?,
+            None => Vec::new(),
+        };
+        if let Some(features) = matches.value_of(FEATURE) {
+            layer_callbacks.extend(named_feature_callbacks(features));
+        }
+        if let Some(misc) = matches.value_of(MISC) {
+            layer_callbacks.extend(named_misc_callbacks(misc));
+        }
+
+        Ok(SignificanceApp {
+            attachment_scores: matches.is_present(ATTACHMENT_SCORES),
+            confidence,
+            default,
+            gold_treebank,
+            layer_callbacks,
+            match_sent_id: matches.is_present(Self::MATCH_SENT_ID),
+            output_format,
+            predicted_treebank_a,
+            predicted_treebank_b,
+            samples,
+            seed,
+            test,
+        })
+    }
+
+    fn run(&self) -> Result<()> {
+        let mut readers: Vec<SentenceIter> = vec![
+            Box::new(open_treebank(&self.gold_treebank)?.sentences()),
+            Box::new(open_treebank(&self.predicted_treebank_a)?.sentences()),
+            Box::new(open_treebank(&self.predicted_treebank_b)?.sentences()),
+        ];
+        if self.match_sent_id {
+            readers = match_treebanks(
+                readers,
+                &[
+                    &self.gold_treebank,
+                    &self.predicted_treebank_a,
+                    &self.predicted_treebank_b,
+                ],
+            )?;
+        }
+        let reader_b = readers.pop().unwrap();
+        let reader_a = readers.pop().unwrap();
+        let gold_reader = readers.pop().unwrap();
+
+        let mut counts = SentenceCounts::default();
+        for ((gold, predicted_a), predicted_b) in
+            gold_reader.into_iter().zip(reader_a).zip(reader_b)
+        {
+            let gold = gold.context("Cannot read sentence from gold treebank")?;
+            let predicted_a = predicted_a.context("Cannot read sentence from treebank A")?;
+            let predicted_b = predicted_b.context("Cannot read sentence from treebank B")?;
+
+            let (metrics, counts_a) = self.sentence_counts(gold.clone(), predicted_a)?;
+            let (_, counts_b) = self.sentence_counts(gold, predicted_b)?;
+            counts.metrics = metrics;
+            counts.a.push(counts_a);
+            counts.b.push(counts_b);
+        }
+
+        ensure!(!counts.a.is_empty(), "The gold standard treebank is empty");
+
+        let mut rng = XorShiftRng::from_seed(self.seed);
+        let records = counts.test(self.test, self.samples, self.confidence, &mut rng);
+
+        if self.output_format == OutputFormat::Text {
+            println!("Metric\tA\tA low\tA high\tB\tB low\tB high\tDiff\tDiff low\tDiff high\tp");
+        }
+
+        write_records(stdout(), self.output_format, &records)?;
+
+        Ok(())
+    }
+}
+
+impl SignificanceApp {
+    /// Evaluate a single sentence, returning the metric names and counts.
+    fn sentence_counts(
+        &self,
+        gold: Sentence,
+        predicted: Sentence,
+    ) -> Result<(Vec<String>, Vec<F1Counts>)> {
+        let pair = SentencePair::new(gold, predicted)?;
+
+        let mut evaluators: Vec<Box<dyn Evaluator>> = Vec::new();
+        if self.attachment_scores {
+            evaluators.push(Box::new(AttachmentEvaluator::new(false, false)));
+        }
+        for (name, callback) in &self.layer_callbacks {
+            evaluators.push(Box::new(LayerEvaluator::new(
+                name.as_str(),
+                callback,
+                self.default.as_deref(),
+            )));
+        }
+
+        let mut metrics = Vec::new();
+        let mut counts = Vec::new();
+        for evaluator in &mut evaluators {
+            evaluator.process(&pair)?;
+            for record in evaluator.scores() {
+                if let (Some(metric), Some(record_counts)) =
+                    (record_metric(&record), F1Counts::from_record(&record))
+                {
+                    metrics.push(metric.to_owned());
+                    counts.push(record_counts);
+                }
+            }
+        }
+
+        Ok((metrics, counts))
+    }
+}
+
+fn open_treebank(path: &str) -> Result<WordReader<Box<dyn BufRead>>> {
+    open_word_reader(&path).context(format!("Cannot open treebank: {}", path))
+}
+
+fn record_metric(record: &Record) -> Option<&str> {
+    match record {
+        Record::Accuracy { metric, .. } | Record::F1 { metric, .. } => Some(metric),
+        _ => None,
+    }
+}
+
+/// Per-sentence counts of every metric for two systems.
+#[derive(Default)]
+struct SentenceCounts {
+    metrics: Vec<String>,
+
+    /// Counts of system A, indexed by sentence, then by metric.
+    a: Vec<Vec<F1Counts>>,
+
+    /// Counts of system B, indexed by sentence, then by metric.
+    b: Vec<Vec<F1Counts>>,
+}
+
+impl SentenceCounts {
+    fn test(&self, test: Test, samples: usize, confidence: f64, rng: &mut impl Rng) -> Vec<Record> {
+        let scores_a = scores(self.a.iter());
+        let scores_b = scores(self.b.iter());
+
+        let (bootstrap_a, bootstrap_b) = self.bootstrap_samples(samples, rng);
+        let p_values = match test {
+            Test::Bootstrap => {
+                self.bootstrap_p_values(&scores_a, &scores_b, &bootstrap_a, &bootstrap_b)
+            }
+            Test::Randomization => self.randomization_p_values(&scores_a, &scores_b, samples, rng),
+        };
+
+        let alpha = 1. - confidence;
+        (0..self.metrics.len())
+            .map(|metric| {
+                let sample_a = bootstrap_a.iter().map(|s| s[metric]).collect::<Vec<_>>();
+                let sample_b = bootstrap_b.iter().map(|s| s[metric]).collect::<Vec<_>>();
+                let sample_diff = sample_a
+                    .iter()
+                    .zip(&sample_b)
+                    .map(|(a, b)| a - b)
+                    .collect::<Vec<_>>();
+
+                let (score_a_low, score_a_high) = interval(sample_a, alpha);
+                let (score_b_low, score_b_high) = interval(sample_b, alpha);
+                let (difference_low, difference_high) = interval(sample_diff, alpha);
+
+                Record::Significance {
+                    metric: self.metrics[metric].clone(),
+                    test: test.name().to_owned(),
+                    score_a: scores_a[metric],
+                    score_a_low,
+                    score_a_high,
+                    score_b: scores_b[metric],
+                    score_b_low,
+                    score_b_high,
+                    difference: scores_a[metric] - scores_b[metric],
+                    difference_low,
+                    difference_high,
+                    p_value: p_values[metric],
+                }
+            })
+            .collect()
+    }
+
+    /// Get the scores of both systems for bootstrap samples of sentences.
+    ///
+    /// Samples are paired: the same sentences are drawn for both systems.
+    fn bootstrap_samples(
+        &self,
+        samples: usize,
+        rng: &mut impl Rng,
+    ) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let n_sentences = self.a.len();
+        let mut bootstrap_a = Vec::with_capacity(samples);
+        let mut bootstrap_b = Vec::with_capacity(samples);
+
+        for _ in 0..samples {
+            let indices = (0..n_sentences)
+                .map(|_| rng.gen_range(0..n_sentences))
+                .collect::<Vec<_>>();
+            bootstrap_a.push(scores(indices.iter().map(|&idx| &self.a[idx])));
+            bootstrap_b.push(scores(indices.iter().map(|&idx| &self.b[idx])));
+        }
+
+        (bootstrap_a, bootstrap_b)
+    }
+
+    /// Compute p-values using the paired bootstrap.
+    ///
+    /// The bootstrap distribution of the difference is shifted to have a
+    /// mean of zero, the null hypothesis. The p-value is the fraction of
+    /// samples where the shifted difference is at least as large as the
+    /// observed difference (two-sided).
+    fn bootstrap_p_values(
+        &self,
+        scores_a: &[f64],
+        scores_b: &[f64],
+        bootstrap_a: &[Vec<f64>],
+        bootstrap_b: &[Vec<f64>],
+    ) -> Vec<f64> {
+        (0..self.metrics.len())
+            .map(|metric| {
+                let observed = scores_a[metric] - scores_b[metric];
+                let extreme = bootstrap_a
+                    .iter()
+                    .zip(bootstrap_b)
+                    .filter(|(a, b)| ((a[metric] - b[metric]) - observed).abs() >= observed.abs())
+                    .count();
+                p_value(extreme, bootstrap_a.len())
+            })
+            .collect()
+    }
+
+    /// Compute p-values using approximate randomization.
+    ///
+    /// In each randomization, the outputs of the systems are swapped for
+    /// each sentence with a probability of 0.5. The p-value is the
+    /// fraction of randomizations where the absolute difference is at
+    /// least as large as the observed difference.
+    fn randomization_p_values(
+        &self,
+        scores_a: &[f64],
+        scores_b: &[f64],
+        samples: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<f64> {
+        let mut extreme = vec![0; self.metrics.len()];
+
+        for _ in 0..samples {
+            let mut shuffled_a = Vec::with_capacity(self.a.len());
+            let mut shuffled_b = Vec::with_capacity(self.b.len());
+            for (a, b) in self.a.iter().zip(&self.b) {
+                if rng.gen() {
+                    shuffled_a.push(b);
+                    shuffled_b.push(a);
+                } else {
+                    shuffled_a.push(a);
+                    shuffled_b.push(b);
+                }
+            }
+
+            let random_a = scores(shuffled_a.into_iter());
+            let random_b = scores(shuffled_b.into_iter());
+            for metric in 0..self.metrics.len() {
+                let observed = (scores_a[metric] - scores_b[metric]).abs();
+                if (random_a[metric] - random_b[metric]).abs() >= observed {
+                    extreme[metric] += 1;
+                }
+            }
+        }
+
+        extreme
+            .into_iter()
+            .map(|extreme| p_value(extreme, samples))
+            .collect()
+    }
+}
+
+/// Get the percentile interval of the given samples.
+fn interval(mut samples: Vec<f64>, alpha: f64) -> (f64, f64) {
+    samples.sort_by(|a, b| a.partial_cmp(b).expect("Score is NaN"));
+    let last = samples.len() - 1;
+    let low = ((alpha / 2.) * last as f64).round() as usize;
+    let high = ((1. - alpha / 2.) * last as f64).round() as usize;
+    (samples[low], samples[high])
+}
+
+/// Compute a p-value with add-one smoothing, so that it is never zero.
+fn p_value(extreme: usize, samples: usize) -> f64 {
+    (extreme + 1) as f64 / (samples + 1) as f64
+}
+
+/// Compute the scores of each metric over the given sentences.
+fn scores<'a>(sentences: impl Iterator<Item = &'a Vec<F1Counts>>) -> Vec<f64> {
+    let mut totals: Vec<F1Counts> = Vec::new();
+    for sentence in sentences {
+        totals.resize(sentence.len(), F1Counts::default());
+        for (total, &counts) in totals.iter_mut().zip(sentence) {
+            *total += counts;
+        }
+    }
+
+    totals.iter().map(F1Counts::f1).collect()
+}
@@ -6,12 +6,14 @@ use clap::{App, Arg, ArgMatches};
 use itertools::Itertools;
 use stdinout::{Input, Output};
 
-use crate::layer::{layer_callback, LayerCallback};
-use crate::traits::{ConlluApp, ConlluPipelineApp};
+use crate::io::{input_reader, output_write, OutputCompression};
+use crate::layer::{parse_layer, LayerCallback};
+use crate::traits::{output_compression, ConlluApp, ConlluPipelineApp};
 
 static LAYER: &str = "LAYER";
 
 pub struct ToTextApp {
+    compression: OutputCompression,
     input: Input,
     output: Output,
     layer_callback: LayerCallback,
@@ -26,20 +28,21 @@ impl ConlluApp for ToTextApp {
             .arg(
                 Arg::with_name(LAYER)
                     .short("l")
-                    .possible_values(&["form", "lemma", "upos", "xpos"])
                     .default_value("form")
-                    .help("Layer to output as text"),
+                    .help("Layer expression to output as text"),
             )
     }
 
     fn parse(matches: &ArgMatches) -> Result<Self> {
         let input = Input::from(matches.value_of(Self::INPUT));
         let output = Output::from(matches.value_of(Self::OUTPUT));
+        let compression = output_compression(matches)?;
 
         let layer = matches.value_of(LAYER).unwrap();
-        let layer_callback = layer_callback(layer).context(format!("Unknown layer: {}", layer))?;
+        let layer_callback = parse_layer(layer).context("Cannot parse layer")?;
 
         Ok(ToTextApp {
+            compression,
             input,
             output,
             layer_callback,
@@ -47,11 +50,9 @@ impl ConlluApp for ToTextApp {
     }
 
     fn run(&self) -> Result<()> {
-        let reader =
-            conllu::io::Reader::new(self.input.buf_read().context("Cannot open treebank")?);
+        let reader = input_reader(&self.input).context("Cannot open treebank")?;
         let mut writer = BufWriter::new(
-            self.output
-                .write()
+            output_write(&self.output, &self.compression)
                 .context("Cannot open output for writing")?,
         );
 
@@ -61,11 +62,10 @@ impl ConlluApp for ToTextApp {
             writeln!(
                 writer,
                 "{}",
-                sentence
-                    .iter()
-                    .filter_map(|n| n.token().map(|t| (*self.layer_callback)(t)
+                (1..sentence.len())
+                    .map(|idx| (*self.layer_callback)(&sentence, idx)
                         .map(Cow::into_owned)
-                        .unwrap_or_else(|| "_".to_owned())))
+                        .unwrap_or_else(|| "_".to_owned()))
                     .join(" ")
             )
             .context("Cannot write sentence")?;
@@ -1,11 +1,57 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{crate_version, App, AppSettings, Arg, ArgMatches};
 
+use crate::io::{Compression, OutputCompression};
+use crate::normalize::ValueNormalizer;
+use crate::unicode::normalization_from;
+
 static DEFAULT_CLAP_SETTINGS: &[AppSettings] = &[
     AppSettings::DontCollapseArgsInUsage,
     AppSettings::UnifiedHelpMessage,
 ];
 
+pub const COMPRESS: &str = "COMPRESS";
+pub const COMPRESSION_LEVEL: &str = "COMPRESSION_LEVEL";
+
+/// Add the global options for compressing output.
+pub fn compression_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name(COMPRESS)
+            .long("compress")
+            .value_name("FORMAT")
+            .possible_values(&["bzip2", "gzip", "none", "xz", "zstd"])
+            .global(true)
+            .help("Compress output (default: infer from the output file extension)"),
+    )
+    .arg(
+        Arg::with_name(COMPRESSION_LEVEL)
+            .long("compression-level")
+            .value_name("LEVEL")
+            .global(true)
+            .help("Compression level of the output"),
+    )
+}
+
+/// Get the output compression from the global compression options.
+pub fn output_compression(matches: &ArgMatches) -> Result<OutputCompression> {
+    let mut compression = OutputCompression::default();
+
+    if let Some(name) = matches.value_of(COMPRESS) {
+        compression = compression.compression(
+            Compression::from_name(name).context(format!("Unknown compression: {}", name))?,
+        );
+    }
+
+    if let Some(level) = matches.value_of(COMPRESSION_LEVEL) {
+        compression = compression.level(level.parse().context(format!(
+            "Cannot parse compression level as an integer: {}",
+            level
+        ))?);
+    }
+
+    Ok(compression)
+}
+
 pub trait ConlluApp
 where
     Self: Sized,
@@ -29,3 +75,64 @@ pub trait ConlluPipelineApp: ConlluApp {
             .arg(Arg::with_name(Self::OUTPUT).help("Output"))
     }
 }
+
+pub trait ConlluMatchingApp: ConlluApp {
+    const MATCH_SENT_ID: &'static str = "MATCH_SENT_ID";
+
+    /// Add the option for matching sentences by their identifiers.
+    fn matching_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(
+            Arg::with_name(Self::MATCH_SENT_ID)
+                .long("match-sent-id")
+                .help("Match sentences by sent_id rather than position"),
+        )
+    }
+}
+
+pub trait ConlluNormalizingApp: ConlluApp {
+    const IGNORE_CASE: &'static str = "IGNORE_CASE";
+    const MAPPING: &'static str = "MAPPING";
+    const UNICODE_NORMALIZATION: &'static str = "UNICODE_NORMALIZATION";
+
+    /// Add the options for normalizing layer values.
+    fn normalizing_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(
+            Arg::with_name(Self::IGNORE_CASE)
+                .short("i")
+                .long("ignore-case")
+                .help("Compare layer values case-insensitively"),
+        )
+        .arg(
+            Arg::with_name(Self::UNICODE_NORMALIZATION)
+                .long("unicode-normalization")
+                .value_name("NORMALIZATION")
+                .possible_values(&["nfd", "nfkd", "nfc", "nfkc", "none"])
+                .help("Unicode normalization of layer values"),
+        )
+        .arg(
+            Arg::with_name(Self::MAPPING)
+                .long("mapping")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Map layer values using a file (value<TAB>replacement)"),
+        )
+    }
+
+    fn value_normalizer(matches: &ArgMatches) -> Result<ValueNormalizer> {
+        let unicode = matches
+            .value_of(Self::UNICODE_NORMALIZATION)
+            .map(|n| normalization_from(n).context(format!("Unknown normalization: {}", n)))
+            .transpose()?;
+
+        let mut normalizer =
+            ValueNormalizer::default().lowercase(matches.is_present(Self::IGNORE_CASE));
+        if let Some(unicode) = unicode {
+            normalizer = normalizer.unicode(unicode);
+        }
+        if let Some(mapping) = matches.value_of(Self::MAPPING) {
+            normalizer = normalizer.mapping_file(mapping)?;
+        }
+
+        Ok(normalizer)
+    }
+}
@@ -10,6 +10,20 @@ pub enum Normalization {
     NFKC,
 }
 
+pub fn normalization_from<S>(value: S) -> Option<Normalization>
+where
+    S: AsRef<str>,
+{
+    match value.as_ref() {
+        "none" => Some(Normalization::None),
+        "nfd" => Some(Normalization::NFD),
+        "nfkd" => Some(Normalization::NFKD),
+        "nfc" => Some(Normalization::NFC),
+        "nfkc" => Some(Normalization::NFKC),
+        _ => None,
+    }
+}
+
 fn normalization_iter<'a, I>(iter: I, norm: Normalization) -> Box<dyn Iterator<Item = char> + 'a>
 where
     I: 'a + Iterator<Item = char>,
@@ -167,6 +181,10 @@ pub fn simplify_unicode_lookup(c: char) -> Conversion {
     }
 }
 
+pub fn normalize(s: &str, norm: Normalization) -> String {
+    normalization_iter(s.chars(), norm).collect()
+}
+
 pub fn simplify_unicode(s: &str, norm: Normalization) -> String {
     normalization_iter(s.chars(), norm).fold(String::with_capacity(s.len()), |mut s, c| {
         match simplify_unicode_lookup(c) {